@@ -0,0 +1,315 @@
+use std::{borrow::Cow, collections::HashMap, fmt::Debug, path::Path, rc::Rc};
+
+use crate::{escape, extract, lexer::{Lexer, LexerMode, TokenKind}};
+
+/// Extensions of source files, whose identifiers are also split into sub-words.
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "rs", "c", "h", "cpp", "hpp", "cs", "java", "kt", "go", "py", "rb", "js", "ts", "jsx", "tsx",
+    "toml", "yaml", "yml", "css", "scss",
+];
+
+/// Extensions of documents whose text `extract` pulls out of the markup.
+const MARKUP_EXTENSIONS: &[&str] = &[
+    "md", "markdown", "html", "htm", "xhtml", "xml", "svg", "rss", "atom", "xsd", "xsl",
+];
+
+/// A decoded token and its byte span in the analyzed text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term<'a> {
+    pub text: Cow<'a, str>,
+    pub start: usize,
+    pub end: usize,
+    pub kind: TokenKind,
+    /// Set on the sub-words of a split identifier, which follow the identifier.
+    pub sub_word: bool,
+}
+
+pub trait Tokenizer: Debug {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Term<'a>>;
+
+    /// Whether terms come out with string escapes like `\n` decoded.
+    fn decodes_escapes(&self) -> bool {
+        return false;
+    }
+}
+
+pub trait TokenFilter: Debug {
+    /// Rewrites a term, or drops it by returning `None`.
+    fn filter<'a>(&self, term: Term<'a>) -> Option<Term<'a>>;
+}
+
+/// A tokenizer followed by a chain of filters.
+#[derive(Debug)]
+pub struct Analyzer {
+    tokenizer: Box<dyn Tokenizer>,
+    filters: Vec<Box<dyn TokenFilter>>,
+}
+
+impl Analyzer {
+
+    pub fn new(tokenizer: impl Tokenizer + 'static) -> Self {
+        return Self { tokenizer: Box::new(tokenizer), filters: Vec::new() };
+    }
+
+    pub fn with_filter(mut self, filter: impl TokenFilter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        return self;
+    }
+
+    pub fn decodes_escapes(&self) -> bool {
+        return self.tokenizer.decodes_escapes();
+    }
+
+    pub fn analyze<'a>(&self, text: &'a str) -> Vec<Term<'a>> {
+        return self.tokenizer.tokenize(text)
+            .into_iter()
+            .filter_map(|term| self.filters.iter().try_fold(term, |term, filter| filter.filter(term)))
+            .collect();
+    }
+}
+
+/// Analyzers by file extension, plus the one queries go through when there
+/// is no document to match them against. Every analyzer folds case the same
+/// way, so query terms meet indexed ones.
+#[derive(Debug, Clone)]
+pub struct Analyzers {
+    by_extension: HashMap<String, Rc<Analyzer>>,
+    default: Rc<Analyzer>,
+    query: Rc<Analyzer>,
+}
+
+impl Default for Analyzers {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+#[allow(dead_code)]
+impl Analyzers {
+
+    /// Source files split identifiers, `.json` lexes inside strings only,
+    /// JSON and markup drop punctuation and everything else is segmented as
+    /// text.
+    pub fn new() -> Self {
+        let text = Rc::new(Analyzer::new(LexerTokenizer(LexerMode::Unicode)).with_filter(Lowercase));
+        let source = Rc::new(Analyzer::new(LexerTokenizer(LexerMode::Identifiers)).with_filter(Lowercase));
+        let json = Rc::new(Analyzer::new(JsonTokenizer).with_filter(DropSymbols).with_filter(Lowercase));
+        let markup = Rc::new(Analyzer::new(LexerTokenizer(LexerMode::Unicode))
+            .with_filter(StripPadding)
+            .with_filter(DropSymbols)
+            .with_filter(Lowercase));
+
+        let mut by_extension = HashMap::new();
+        for extension in SOURCE_EXTENSIONS {
+            by_extension.insert(extension.to_string(), source.clone());
+        }
+        for extension in MARKUP_EXTENSIONS {
+            by_extension.insert(extension.to_string(), markup.clone());
+        }
+        by_extension.insert("json".to_owned(), json);
+
+        return Self { by_extension, default: text.clone(), query: text };
+    }
+
+    pub fn register(&mut self, extension: &str, analyzer: Analyzer) {
+        self.by_extension.insert(extension.to_owned(), Rc::new(analyzer));
+    }
+
+    pub fn for_path(&self, path: &Path) -> &Analyzer {
+        return path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| self.by_extension.get(e))
+            .unwrap_or(&self.default);
+    }
+
+    pub fn query(&self) -> &Analyzer {
+        return &self.query;
+    }
+
+    /// The analyzers of `paths`, each once, or the query analyzer if there
+    /// are no paths.
+    pub fn for_paths<'p>(&self, paths: impl Iterator<Item = &'p Path>) -> Vec<&Analyzer> {
+        let mut analyzers: Vec<&Analyzer> = Vec::new();
+        for path in paths {
+            let analyzer = self.for_path(path);
+            if !analyzers.iter().any(|a| std::ptr::eq(*a, analyzer)) {
+                analyzers.push(analyzer);
+            }
+        }
+        if analyzers.is_empty() {
+            analyzers.push(&self.query);
+        }
+        return analyzers;
+    }
+}
+
+/// The `Lexer` in one mode, with escapes decoded. Unicode segmentation
+/// never keeps a backslash inside a word, so text has none to decode.
+#[derive(Debug)]
+pub struct LexerTokenizer(pub LexerMode);
+
+impl Tokenizer for LexerTokenizer {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Term<'a>> {
+        return lex_terms(text, 0, self.0);
+    }
+
+    fn decodes_escapes(&self) -> bool {
+        return self.0 != LexerMode::Unicode;
+    }
+}
+
+/// Terms of `text`, with spans shifted by `offset`.
+fn lex_terms(text: &str, offset: usize, mode: LexerMode) -> Vec<Term<'_>> {
+    let mut terms = Vec::new();
+    // span of the last token that wasn't a sub-word
+    let mut whole = (0, 0);
+
+    for token in Lexer::with_mode(text, mode).spanned() {
+        let (start, end) = (token.start, token.end());
+        let sub_word = start >= whole.0 && end <= whole.1;
+        if !sub_word {
+            whole = (start, end);
+        }
+
+        let text = escape::unescape(token.text);
+        let kind = TokenKind::of(&text);
+        terms.push(Term { text, start: start + offset, end: end + offset, kind, sub_word });
+    }
+    return terms;
+}
+
+/// JSON, where structural punctuation becomes symbols, the contents of
+/// strings are lexed as source with escapes decoded and quotes are dropped.
+#[derive(Debug)]
+pub struct JsonTokenizer;
+
+impl Tokenizer for JsonTokenizer {
+    fn tokenize<'a>(&self, text: &'a str) -> Vec<Term<'a>> {
+        let bytes = text.as_bytes();
+        let mut terms = Vec::new();
+        let mut i = 0;
+
+        while i < bytes.len() {
+            match bytes[i] {
+                b'"' => {
+                    let end = string_end(bytes, i + 1);
+                    terms.extend(lex_terms(&text[i + 1..end], i + 1, LexerMode::Identifiers));
+                    i = end + 1;
+                }
+                b'{' | b'}' | b'[' | b']' | b',' | b':' => {
+                    let text = Cow::Borrowed(&text[i..i + 1]);
+                    terms.push(Term { text, start: i, end: i + 1, kind: TokenKind::Symbol, sub_word: false });
+                    i += 1;
+                }
+                c if c.is_ascii_whitespace() => i += 1,
+                _ => {
+                    let end = bytes[i..].iter()
+                        .position(|c| c.is_ascii_whitespace() || b"{}[],:\"".contains(c))
+                        .map(|n| i + n)
+                        .unwrap_or(bytes.len());
+                    terms.extend(lex_terms(&text[i..end], i, LexerMode::Identifiers));
+                    i = end;
+                }
+            }
+        }
+        return terms;
+    }
+
+    fn decodes_escapes(&self) -> bool {
+        return true;
+    }
+}
+
+/// Index of the quote closing a string that starts at `from`.
+fn string_end(bytes: &[u8], from: usize) -> usize {
+    let mut i = from;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i,
+            _ => i += 1,
+        }
+    }
+    return bytes.len();
+}
+
+/// Lowercases terms, so matching ignores case.
+#[derive(Debug)]
+pub struct Lowercase;
+
+impl TokenFilter for Lowercase {
+    fn filter<'a>(&self, mut term: Term<'a>) -> Option<Term<'a>> {
+        if term.text.chars().any(char::is_uppercase) {
+            term.text = Cow::Owned(term.text.to_lowercase());
+        }
+        return Some(term);
+    }
+}
+
+/// Drops punctuation and other symbols.
+#[derive(Debug)]
+pub struct DropSymbols;
+
+impl TokenFilter for DropSymbols {
+    fn filter<'a>(&self, term: Term<'a>) -> Option<Term<'a>> {
+        return if term.kind == TokenKind::Symbol { None } else { Some(term) };
+    }
+}
+
+/// Removes the padding `extract` puts after decoded entities.
+#[derive(Debug)]
+pub struct StripPadding;
+
+impl TokenFilter for StripPadding {
+    fn filter<'a>(&self, mut term: Term<'a>) -> Option<Term<'a>> {
+        if term.text.contains(extract::is_padding) {
+            term.text = Cow::Owned(term.text.replace(extract::is_padding, ""));
+            term.kind = TokenKind::of(&term.text);
+        }
+        return Some(term);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(path: &str, text: &str) -> Vec<(String, usize, usize, bool)> {
+        return Analyzers::new().for_path(Path::new(path)).analyze(text).into_iter()
+            .map(|t| (t.text.into_owned(), t.start, t.end, t.sub_word))
+            .collect();
+    }
+
+    fn span(text: &str, start: usize, end: usize, sub_word: bool) -> (String, usize, usize, bool) {
+        return (text.to_owned(), start, end, sub_word);
+    }
+
+    #[test]
+    fn sub_words_follow_their_identifier_inside_its_span() {
+        assert_eq!(spans("a.rs", "let parseHTTP_2 = 1;"), [
+            span("let", 0, 3, false),
+            span("parsehttp_2", 4, 15, false),
+            span("parse", 4, 9, true),
+            span("http", 9, 13, true),
+            span("2", 14, 15, true),
+            span("=", 16, 17, false),
+            span("1", 18, 19, false),
+            span(";", 19, 20, false),
+        ]);
+    }
+
+    #[test]
+    fn sub_word_spans_are_raw_and_decoded_text_is_not() {
+        assert_eq!(spans("a.rs", r"caf\u00e9Noir"), [
+            span("cafénoir", 0, 13, false),
+            span("café", 0, 9, true),
+            span("noir", 9, 13, true),
+        ]);
+        // in JSON, spans point into the document past the opening quote
+        assert_eq!(spans("a.json", r#"{"k": "snake_case"}"#)[1..4], [
+            span("snake_case", 7, 17, false),
+            span("snake", 7, 12, true),
+            span("case", 13, 17, true),
+        ]);
+    }
+}
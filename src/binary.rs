@@ -0,0 +1,89 @@
+//! Little helpers for the compact binary formats: unsigned LEB128 varints
+//! and length-prefixed strings.
+
+use std::collections::BTreeSet;
+
+use crate::{documents::DocId, error::{Error, Result}};
+
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub fn write_str(out: &mut Vec<u8>, value: &str) {
+    write_varint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// A sorted set of doc ids: the count, then the gaps between them.
+pub fn write_doc_ids<'d>(out: &mut Vec<u8>, docs: impl ExactSizeIterator<Item = &'d DocId>) {
+    write_varint(out, docs.len() as u64);
+    let mut previous = 0;
+    for doc in docs {
+        write_varint(out, (*doc - previous) as u64);
+        previous = *doc;
+    }
+}
+
+pub struct Reader<'a> {
+    content: &'a [u8],
+}
+
+impl <'a> Reader<'a> {
+
+    pub fn new(content: &'a [u8]) -> Self {
+        return Self { content };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.content.is_empty();
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.content.len() < n {
+            return Err(Error::IndexFormat("Unexpected end of data".to_owned()));
+        }
+        let (taken, rest) = self.content.split_at(n);
+        self.content = rest;
+        return Ok(taken);
+    }
+
+    pub fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.bytes(1)?[0];
+            if shift > 63 {
+                return Err(Error::IndexFormat("Varint too long".to_owned()));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    pub fn str(&mut self) -> Result<&'a str> {
+        let len = self.varint()? as usize;
+        return std::str::from_utf8(self.bytes(len)?)
+            .map_err(|_| Error::IndexFormat("Invalid utf-8 in string".to_owned()));
+    }
+
+    pub fn doc_ids(&mut self) -> Result<BTreeSet<DocId>> {
+        let mut docs = BTreeSet::new();
+        let mut previous = 0;
+        for _ in 0..self.varint()? {
+            previous += self.varint()? as DocId;
+            docs.insert(previous);
+        }
+        return Ok(docs);
+    }
+}
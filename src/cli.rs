@@ -0,0 +1,560 @@
+use std::{
+    io::{BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    documents::DocId,
+    error::{Error, Result},
+    index_files::{index, Corpus, ErrorPolicy, Granularity, IndexOptions},
+    log::{self, info, Level},
+    lsp::LanguageServer,
+    parse::JsonValue,
+    repl::Repl,
+    report,
+    server,
+    search::{document_snippet, explain, file_hits, search_term},
+    snippet::{Highlight, SNIPPET_WIDTH},
+    trie::Ranking,
+};
+
+pub const USAGE: &str = "\
+Usage: fji-tf-idf <command> [options]
+
+Commands:
+  index <root> -o <file>   Index the files under <root> and save the index
+  search <query>           Rank documents for <query>
+  stats                    Show corpus statistics, top terms and largest documents (-n)
+  complete [<prefix>]      Complete <prefix>, or prefixes read from stdin
+  terms [<prefix>]         List indexed terms with their frequencies
+  dump-doc <path>          Show what was indexed for one document
+  repl                     Search interactively, indexing or loading once
+  serve                    Answer /search, /complete, /doc and /stats over HTTP
+  lsp                      Run a language server on stdio; without -i or -r it
+                           indexes the workspace root the editor sends
+
+Options:
+  -i, --index <file>       Use an index saved by `index`
+  -r, --root <dir>         Index <dir> on the fly instead of loading an index
+  -o, --output <file>      Where `index` saves the index
+  -a, --address <addr>     Where `serve` listens (default: 127.0.0.1:7878)
+  -e, --ext <ext,...>      Extensions of the files to index (default: json)
+  -g, --granularity <g>    What one document is: file, dir (a folder's files),
+                           top (a top-level folder) or a folder depth N
+                           (default: file)
+  -j, --join               Same as --granularity dir
+      --no-ngrams          Skip the trigram index behind wildcards and regexes
+      --fail-fast          Stop at an unreadable file instead of skipping it
+  -f, --format <format>    text or json; search also takes html (default: text)
+  -n, --limit <n>          How many results or terms to show (default: 15)
+      --explain            Show how each search result's score was computed
+      --ranking <df|tf>    Rank completions by document or total frequency
+  -q, --quiet              Only print errors to stderr
+  -v, --verbose            List every file indexed; twice (-vv) for debug
+                           details and timings
+      --log-level <level>  quiet, normal, verbose or debug (default: normal)
+  -h, --help               Show this help
+
+`fji-tf-idf <root> <query>` is short for `fji-tf-idf search -r <root> <query>`,
+and `fji-tf-idf <root>` for `fji-tf-idf repl -r <root>`.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Index { root: PathBuf },
+    Search { query: String },
+    Stats,
+    Complete { prefix: Option<String> },
+    Terms { prefix: Option<String> },
+    DumpDoc { path: PathBuf },
+    Repl,
+    Serve,
+    Lsp,
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Html,
+}
+
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub command: Command,
+    pub index: Option<PathBuf>,
+    pub root: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub address: String,
+    pub extensions: Vec<String>,
+    pub granularity: Granularity,
+    pub ngrams: bool,
+    pub fail_fast: bool,
+    pub format: OutputFormat,
+    pub limit: usize,
+    pub explain: bool,
+    pub ranking: Ranking,
+    pub log_level: Level,
+}
+
+impl Args {
+    /// Parses everything after the program name.
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut parsed = Args {
+            command: Command::Help,
+            index: None,
+            root: None,
+            output: None,
+            address: "127.0.0.1:7878".to_owned(),
+            extensions: vec!["json".to_owned()],
+            granularity: Granularity::File,
+            ngrams: true,
+            fail_fast: false,
+            format: OutputFormat::Text,
+            limit: 15,
+            explain: false,
+            ranking: Ranking::DocumentFrequency,
+            log_level: Level::Normal,
+        };
+
+        let mut positional = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if arg.starts_with("--") => (flag, Some(value.to_owned())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = |name: &str| -> Result<String> {
+                return inline.clone()
+                    .or_else(|| args.next().cloned())
+                    .ok_or_else(|| Error::Usage(format!("{} needs a value", name)));
+            };
+
+            match flag {
+                "-h" | "--help" => parsed.command = Command::Help,
+                "-i" | "--index" => parsed.index = Some(PathBuf::from(value(flag)?)),
+                "-r" | "--root" => parsed.root = Some(PathBuf::from(value(flag)?)),
+                "-o" | "--output" => parsed.output = Some(PathBuf::from(value(flag)?)),
+                "-a" | "--address" => parsed.address = value(flag)?,
+                "-e" | "--ext" => {
+                    parsed.extensions = value(flag)?.split(',')
+                        .map(|e| e.trim().trim_start_matches('.').to_owned())
+                        .filter(|e| !e.is_empty())
+                        .collect();
+                }
+                "-g" | "--granularity" => {
+                    let granularity = value(flag)?;
+                    parsed.granularity = Granularity::parse(&granularity)
+                        .ok_or_else(|| Error::Usage(format!("Unknown granularity: {}", granularity)))?;
+                }
+                "-j" | "--join" => parsed.granularity = Granularity::Directory,
+                "--no-ngrams" => parsed.ngrams = false,
+                "--explain" => parsed.explain = true,
+                "--fail-fast" => parsed.fail_fast = true,
+                "-f" | "--format" => {
+                    parsed.format = match value(flag)?.as_str() {
+                        "text" => OutputFormat::Text,
+                        "json" => OutputFormat::Json,
+                        "html" => OutputFormat::Html,
+                        other => return Err(Error::Usage(format!("Unknown format: {}", other))),
+                    };
+                }
+                "-n" | "--limit" => {
+                    let limit = value(flag)?;
+                    parsed.limit = limit.parse()
+                        .map_err(|_| Error::Usage(format!("Not a number: {}", limit)))?;
+                }
+                "--ranking" => parsed.ranking = parse_ranking(&value(flag)?)?,
+                "-q" | "--quiet" => parsed.log_level = Level::Quiet,
+                "-v" | "--verbose" => parsed.log_level = parsed.log_level.louder(),
+                "-vv" => parsed.log_level = Level::Debug,
+                "--log-level" => {
+                    let level = value(flag)?;
+                    parsed.log_level = Level::parse(&level)
+                        .ok_or_else(|| Error::Usage(format!("Unknown log level: {}", level)))?;
+                }
+                "--complete" => {
+                    parsed.ranking = parse_ranking(inline.as_deref().unwrap_or("df"))?;
+                    positional.push("complete".to_owned());
+                }
+                _ if flag.starts_with('-') && flag.len() > 1 => {
+                    return Err(Error::Usage(format!("Unknown option: {}", flag)));
+                }
+                _ => positional.push(arg.clone()),
+            }
+            if matches!(flag, "-h" | "--help") {
+                return Ok(parsed);
+            }
+        }
+
+        parsed.command = parsed.command_from(positional)?;
+        return Ok(parsed);
+    }
+
+    fn command_from(&mut self, mut positional: Vec<String>) -> Result<Command> {
+        if positional.is_empty() {
+            return Ok(Command::Help);
+        }
+
+        let name = positional.remove(0);
+        let rest = positional.join(" ");
+        let command = match name.as_str() {
+            "index" => {
+                let root = positional.first()
+                    .ok_or_else(|| Error::Usage("index needs a <root>".to_owned()))?;
+                Command::Index { root: PathBuf::from(root) }
+            }
+            "search" if !rest.is_empty() => Command::Search { query: rest },
+            "search" => return Err(Error::Usage("search needs a <query>".to_owned())),
+            "stats" => Command::Stats,
+            "complete" => Command::Complete { prefix: Some(rest).filter(|r| !r.is_empty()) },
+            "terms" => Command::Terms { prefix: Some(rest).filter(|r| !r.is_empty()) },
+            "dump-doc" => {
+                let path = positional.first()
+                    .ok_or_else(|| Error::Usage("dump-doc needs a <path>".to_owned()))?;
+                Command::DumpDoc { path: PathBuf::from(path) }
+            }
+            "repl" => Command::Repl,
+            "serve" => Command::Serve,
+            "lsp" => Command::Lsp,
+            "help" => Command::Help,
+            // the old `<root> <query>` and `<root> --complete` forms
+            root if self.root.is_none() && self.index.is_none() && Path::new(root).is_dir() => {
+                self.root = Some(PathBuf::from(root));
+                match positional.first().map(String::as_str) {
+                    Some("complete") => Command::Complete { prefix: None },
+                    Some(_) => Command::Search { query: rest },
+                    None => Command::Repl,
+                }
+            }
+            other => return Err(Error::Usage(format!("Unknown command: {}", other))),
+        };
+        return Ok(command);
+    }
+
+    fn options(&self) -> IndexOptions {
+        let mut options = IndexOptions::new(&[]);
+        options.extensions = self.extensions.clone();
+        options.granularity = self.granularity;
+        options.ngrams = self.ngrams;
+        options.on_error = if self.fail_fast { ErrorPolicy::FailFast } else { ErrorPolicy::Skip };
+        return options;
+    }
+
+    /// The saved index given with `-i`, or a fresh one of the `-r` folder.
+    fn corpus(&self) -> Result<Corpus> {
+        return match (&self.index, &self.root) {
+            (Some(file), _) => Corpus::load(file, self.options()),
+            (None, Some(root)) => index(root, self.options()),
+            (None, None) => Err(Error::Usage("Pass a saved index with -i or a folder with -r".to_owned())),
+        };
+    }
+}
+
+/// Exit status for a failed run: 2 for a command line that doesn't make
+/// sense, 1 for anything else.
+pub fn exit_code(error: &Error) -> i32 {
+    return match error {
+        Error::Usage(_) => 2,
+        _ => 1,
+    };
+}
+
+fn parse_ranking(name: &str) -> Result<Ranking> {
+    return match name {
+        "df" => Ok(Ranking::DocumentFrequency),
+        "tf" => Ok(Ranking::TotalFrequency),
+        other => Err(Error::Usage(format!("Unknown completion ranking: {}", other))),
+    };
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    log::set_level(args.log_level);
+    return match &args.command {
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::Index { root } => {
+            let output = args.output.as_ref()
+                .ok_or_else(|| Error::Usage("index needs -o <file>".to_owned()))?;
+            let corpus = index(root, args.options())?;
+            corpus.save(output)?;
+            info!("Saved {} documents to {}", corpus.documents.len(), output.display());
+            Ok(())
+        }
+        Command::Search { query } => search(args, &args.corpus()?, query),
+        Command::Stats => stats(args, &args.corpus()?),
+        Command::Complete { prefix: Some(prefix) } => {
+            let corpus = args.corpus()?;
+            print_completions(args, &corpus, prefix);
+            Ok(())
+        }
+        Command::Complete { prefix: None } => complete_interactive(args, &args.corpus()?),
+        Command::Terms { prefix } => terms(args, &args.corpus()?, prefix.as_deref().unwrap_or("")),
+        Command::DumpDoc { path } => dump_doc(args, &args.corpus()?, path),
+        Command::Repl => Repl::new(&args.corpus()?, args.limit).run(),
+        Command::Serve => server::serve(&args.corpus()?, &args.address),
+        Command::Lsp => {
+            let corpus = match args.index.is_some() || args.root.is_some() {
+                true => Some(args.corpus()?),
+                false => None,
+            };
+            LanguageServer::new(corpus, args.options()).run()
+        }
+    };
+}
+
+fn search(args: &Args, corpus: &Corpus, query: &str) -> Result<()> {
+    let results = search_term(query.to_owned(), corpus)?;
+    let top = results.iter().rev().take(args.limit);
+    // grouped documents drill down into the files the hits are in
+    let files = |id: DocId| -> Vec<(PathBuf, usize)> {
+        if corpus.options.granularity == Granularity::File {
+            return Vec::new();
+        }
+        return file_hits(query, corpus, id).unwrap_or_default();
+    };
+
+    match args.format {
+        OutputFormat::Text => {
+            let highlight = if std::io::stdout().is_terminal() { Highlight::Ansi } else { Highlight::Plain };
+            for (id, score) in top {
+                println!("{} -> {}", corpus.documents.path(*id).display(), score);
+                if let Some((_, snippet)) = document_snippet(query, corpus, *id, SNIPPET_WIDTH) {
+                    println!("    {}", snippet.render(highlight));
+                }
+                for (file, hits) in files(*id) {
+                    println!("    - {} ({} hits)", file.display(), hits);
+                }
+                if args.explain {
+                    for line in explain(query, corpus, *id)?.to_string().lines() {
+                        println!("    {}", line);
+                    }
+                }
+            }
+        }
+        OutputFormat::Json => {
+            let results = top.map(|(id, score)| report::result(query, corpus, *id, *score, args.explain));
+            println!("{}", JsonValue::ArrayValue(results.collect()));
+        }
+        OutputFormat::Html => {
+            println!("<ol class=\"results\">");
+            for (id, score) in top {
+                let path = corpus.documents.path(*id).display().to_string();
+                println!("  <li><a href=\"{}\">{}</a> <span class=\"score\">{:.4}</span>", escape_html(&path), escape_html(&path), score);
+                if let Some((_, snippet)) = document_snippet(query, corpus, *id, SNIPPET_WIDTH) {
+                    println!("    <p>{}</p>", snippet.render(Highlight::Html));
+                }
+                let files = files(*id);
+                if !files.is_empty() {
+                    println!("    <ul class=\"files\">");
+                    for (file, hits) in files {
+                        let file = escape_html(&file.display().to_string());
+                        println!("      <li><a href=\"{}\">{}</a> <span class=\"hits\">{}</span></li>", file, file, hits);
+                    }
+                    println!("    </ul>");
+                }
+                if args.explain {
+                    println!("    <pre class=\"explanation\">{}</pre>", escape_html(&explain(query, corpus, *id)?.to_string()));
+                }
+                println!("  </li>");
+            }
+            println!("</ol>");
+        }
+    }
+    return Ok(());
+}
+
+fn stats(args: &Args, corpus: &Corpus) -> Result<()> {
+    let stats = report::stats(corpus, args.limit);
+    match args.format {
+        OutputFormat::Json => println!("{}", JsonValue::object(stats)),
+        _ => {
+            for (name, value) in stats.iter() {
+                let name = name.replace('_', " ");
+                match value {
+                    // lists of terms or documents get a line each
+                    JsonValue::ArrayValue(items) if matches!(items.first(), Some(JsonValue::ObjectValue(_))) => {
+                        println!("{}:", name);
+                        for item in items {
+                            println!("  {}", report::plain(item));
+                        }
+                    }
+                    value => println!("{:<22} {}", name, report::plain(value)),
+                }
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn print_completions(args: &Args, corpus: &Corpus, prefix: &str) {
+    match args.format {
+        OutputFormat::Json => println!("{}", report::completions(corpus, prefix, args.limit, args.ranking)),
+        _ => {
+            for (word, score) in corpus.triee.complete(&prefix.trim().to_lowercase(), args.limit, args.ranking) {
+                println!("{} ({})", word, score);
+            }
+        }
+    }
+}
+
+fn complete_interactive(args: &Args, corpus: &Corpus) -> Result<()> {
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    let stdout_error = |e| Error::io(Path::new("<stdout>"), e);
+
+    print!("> ");
+    stdout.flush().map_err(stdout_error)?;
+    for line in stdin.lock().lines() {
+        let line = line.map_err(|e| Error::io(Path::new("<stdin>"), e))?;
+        print_completions(args, corpus, &line);
+        print!("> ");
+        stdout.flush().map_err(stdout_error)?;
+    }
+    println!();
+
+    return Ok(());
+}
+
+fn terms(args: &Args, corpus: &Corpus, prefix: &str) -> Result<()> {
+    let terms = report::term_list(corpus, prefix, args.limit);
+    match args.format {
+        OutputFormat::Json => println!("{}", report::terms(&terms)),
+        _ => {
+            for (word, df, tf) in terms {
+                println!("{:<24} {:>6} docs {:>8} times", word, df, tf);
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn dump_doc(args: &Args, corpus: &Corpus, path: &Path) -> Result<()> {
+    let id = report::find_document(corpus, path)
+        .ok_or_else(|| Error::Usage(format!("{} is not an indexed document", path.display())))?;
+
+    match args.format {
+        OutputFormat::Json => println!("{}", report::document_detail(corpus, id)),
+        _ => {
+            let mut terms = corpus.index.get(&id)
+                .map(|doc| doc.iter().collect::<Vec<_>>())
+                .unwrap_or_default();
+            terms.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+            let info = corpus.documents.get(id).expect("Found document id");
+            println!("path     {}", info.path.display());
+            println!("id       {}", id);
+            println!("title    {}", info.title.as_deref().unwrap_or("-"));
+            println!("size     {}", info.size);
+            println!("length   {}", info.length);
+            println!("files    {}", info.files.len());
+            if info.files.len() > 1 || info.files.first().map(|f| &f.path) != Some(&info.path) {
+                for file in info.files.iter() {
+                    println!("  {}", file.path.display());
+                }
+            }
+            println!("terms    {}", terms.len());
+            for (term, count) in terms.iter().take(args.limit) {
+                println!("  {:<24} {}", term, count);
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn escape_html(text: &str) -> String {
+    return text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        return Args::parse(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>());
+    }
+
+    fn command(args: &[&str]) -> Command {
+        return parse(args).unwrap().command;
+    }
+
+    fn usage_error(args: &[&str]) -> String {
+        let error = parse(args).unwrap_err();
+        assert_eq!(exit_code(&error), 2, "{}", error);
+        return error.to_string();
+    }
+
+    #[test]
+    fn subcommands() {
+        assert_eq!(command(&["index", "docs", "-o", "out.fji"]), Command::Index { root: PathBuf::from("docs") });
+        assert_eq!(command(&["search", "-i", "x.fji", "rust", "lang"]), Command::Search { query: "rust lang".to_owned() });
+        assert_eq!(command(&["stats"]), Command::Stats);
+        assert_eq!(command(&["complete", "ru"]), Command::Complete { prefix: Some("ru".to_owned()) });
+        assert_eq!(command(&["complete"]), Command::Complete { prefix: None });
+        assert_eq!(command(&["terms"]), Command::Terms { prefix: None });
+        assert_eq!(command(&["dump-doc", "a.json"]), Command::DumpDoc { path: PathBuf::from("a.json") });
+        assert_eq!(command(&["repl"]), Command::Repl);
+        assert_eq!(command(&["serve", "-a", "0.0.0.0:80"]), Command::Serve);
+        assert_eq!(command(&["lsp"]), Command::Lsp);
+        assert_eq!(command(&[]), Command::Help);
+        assert_eq!(command(&["search", "--help", "--bogus"]), Command::Help);
+    }
+
+    #[test]
+    fn options() {
+        let args = parse(&[
+            "search", "-e", ".md, txt", "-g", "top", "--no-ngrams", "--fail-fast", "-f", "json",
+            "-n", "5", "--ranking=tf", "--explain", "-vv", "q",
+        ]).unwrap();
+        assert_eq!(args.extensions, ["md", "txt"]);
+        assert_eq!(args.granularity, Granularity::Depth(1));
+        assert!(!args.ngrams && args.fail_fast && args.explain);
+        assert_eq!(args.format, OutputFormat::Json);
+        assert_eq!(args.limit, 5);
+        assert_eq!(args.ranking, Ranking::TotalFrequency);
+        assert_eq!(args.log_level, Level::Debug);
+
+        assert_eq!(parse(&["stats", "-j"]).unwrap().granularity, Granularity::Directory);
+        assert_eq!(parse(&["stats", "-v"]).unwrap().log_level, Level::Verbose);
+        assert_eq!(parse(&["stats", "-q"]).unwrap().log_level, Level::Quiet);
+        assert_eq!(parse(&["stats", "--log-level=quiet", "-v"]).unwrap().log_level, Level::Normal);
+    }
+
+    #[test]
+    fn legacy_positional_forms() {
+        let dir = std::env::temp_dir();
+        let root = dir.to_str().unwrap();
+
+        let args = parse(&[root, "rust", "lang"]).unwrap();
+        assert_eq!(args.command, Command::Search { query: "rust lang".to_owned() });
+        assert_eq!(args.root.as_deref(), Some(dir.as_path()));
+
+        let args = parse(&[root, "--complete=tf"]).unwrap();
+        assert_eq!(args.command, Command::Complete { prefix: None });
+        assert_eq!(args.ranking, Ranking::TotalFrequency);
+
+        assert_eq!(command(&[root]), Command::Repl);
+        // only without an explicit index or root
+        assert!(usage_error(&["-i", "x.fji", root, "rust"]).contains("Unknown command"));
+    }
+
+    #[test]
+    fn usage_errors() {
+        assert!(usage_error(&["frobnicate"]).contains("Unknown command: frobnicate"));
+        assert!(usage_error(&["stats", "--bogus"]).contains("Unknown option: --bogus"));
+        assert!(usage_error(&["stats", "-i"]).contains("-i needs a value"));
+        assert!(usage_error(&["stats", "-n", "ten"]).contains("Not a number: ten"));
+        assert!(usage_error(&["stats", "-g", "sideways"]).contains("Unknown granularity"));
+        assert!(usage_error(&["stats", "-f", "xml"]).contains("Unknown format"));
+        assert!(usage_error(&["stats", "--ranking", "idf"]).contains("Unknown completion ranking"));
+        assert!(usage_error(&["search"]).contains("search needs a <query>"));
+        assert!(usage_error(&["index"]).contains("index needs a <root>"));
+        assert!(usage_error(&["dump-doc"]).contains("dump-doc needs a <path>"));
+
+        assert_eq!(exit_code(&Error::Parse("x".to_owned())), 1);
+        let missing = Args::parse(&["stats".to_owned()]).unwrap().corpus().err().unwrap();
+        assert_eq!(exit_code(&missing), 2);
+    }
+}
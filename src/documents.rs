@@ -0,0 +1,153 @@
+use std::{path::{Path, PathBuf}, collections::HashMap, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use crate::{binary::{self, Reader}, error::Result};
+
+pub type DocId = u32;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct DocumentInfo {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub length: usize,
+    pub title: Option<String>,
+    /// Files merged into the document, just `path` itself unless documents
+    /// are grouped (see `Granularity`).
+    pub files: Vec<FileInfo>,
+}
+
+/// One file of a document, with its share of the size and length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileInfo {
+    pub path: PathBuf,
+    pub size: u64,
+    pub length: usize,
+}
+
+impl DocumentInfo {
+    pub fn new(path: PathBuf) -> Self {
+        return Self { path, size: 0, modified: None, length: 0, title: None, files: Vec::new() };
+    }
+}
+
+/// Maps dense `DocId`s to document metadata. Ids of removed documents are
+/// never handed out again, so postings still referring to them can't be
+/// confused with a newer document.
+#[derive(Default)]
+pub struct DocumentRegistry {
+    docs: Vec<Option<DocumentInfo>>,
+    ids: HashMap<PathBuf, DocId>,
+}
+
+#[allow(dead_code)]
+impl DocumentRegistry {
+
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Returns the id of `path`, registering it if it is not known yet.
+    pub fn register(&mut self, path: PathBuf) -> DocId {
+        if let Some(id) = self.ids.get(&path) {
+            return *id;
+        }
+
+        let id = self.docs.len() as DocId;
+        self.docs.push(Some(DocumentInfo::new(path.clone())));
+        self.ids.insert(path, id);
+        return id;
+    }
+
+    pub fn remove(&mut self, id: DocId) -> Option<DocumentInfo> {
+        let info = self.docs.get_mut(id as usize)?.take()?;
+        self.ids.remove(&info.path);
+        return Some(info);
+    }
+
+    pub fn id_of(&self, path: &Path) -> Option<DocId> {
+        return self.ids.get(path).copied();
+    }
+
+    pub fn get(&self, id: DocId) -> Option<&DocumentInfo> {
+        return self.docs.get(id as usize)?.as_ref();
+    }
+
+    pub fn get_mut(&mut self, id: DocId) -> Option<&mut DocumentInfo> {
+        return self.docs.get_mut(id as usize)?.as_mut();
+    }
+
+    /// Path of a live document, panics on a removed or unknown id.
+    pub fn path(&self, id: DocId) -> &Path {
+        return &self.get(id).expect("Unknown document id").path;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (DocId, &DocumentInfo)> {
+        return self.docs.iter()
+            .enumerate()
+            .filter_map(|(id, d)| d.as_ref().map(|d| (id as DocId, d)));
+    }
+
+    pub fn len(&self) -> usize {
+        return self.ids.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.ids.is_empty();
+    }
+
+    /// Every slot in id order, removed ones included, so ids survive a reload.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        binary::write_varint(out, self.docs.len() as u64);
+        for doc in self.docs.iter() {
+            let info = match doc {
+                Some(info) => info,
+                None => {
+                    binary::write_varint(out, 0);
+                    continue;
+                }
+            };
+            binary::write_varint(out, 1);
+            binary::write_str(out, &info.path.to_string_lossy());
+            binary::write_varint(out, info.size);
+            let modified = info.modified.and_then(|m| m.duration_since(UNIX_EPOCH).ok());
+            binary::write_varint(out, modified.map(|m| m.as_millis() as u64 + 1).unwrap_or(0));
+            binary::write_varint(out, info.length as u64);
+            binary::write_str(out, info.title.as_deref().unwrap_or(""));
+            binary::write_varint(out, info.files.len() as u64);
+            for file in info.files.iter() {
+                binary::write_str(out, &file.path.to_string_lossy());
+                binary::write_varint(out, file.size);
+                binary::write_varint(out, file.length as u64);
+            }
+        }
+    }
+
+    pub fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let mut registry = Self::new();
+        for id in 0..reader.varint()? {
+            if reader.varint()? == 0 {
+                registry.docs.push(None);
+                continue;
+            }
+
+            let path = PathBuf::from(reader.str()?);
+            let size = reader.varint()?;
+            let modified = match reader.varint()? {
+                0 => None,
+                millis => Some(UNIX_EPOCH + Duration::from_millis(millis - 1)),
+            };
+            let length = reader.varint()? as usize;
+            let title = Some(reader.str()?).filter(|t| !t.is_empty()).map(str::to_owned);
+            let mut files = Vec::new();
+            for _ in 0..reader.varint()? {
+                let path = PathBuf::from(reader.str()?);
+                files.push(FileInfo { path, size: reader.varint()?, length: reader.varint()? as usize });
+            }
+
+            registry.ids.insert(path.clone(), id as DocId);
+            registry.docs.push(Some(DocumentInfo { path, size, modified, length, title, files }));
+        }
+        return Ok(registry);
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use crate::error::{Error, Result};
+
+/// How many leading bytes binary sniffing looks at.
+const SNIFF_LENGTH: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+/// Reads a text file: UTF-8 (a BOM is dropped), UTF-16 with a BOM, or
+/// Latin-1 when the bytes aren't valid UTF-8. Files that look binary are
+/// refused with `Error::Binary`.
+pub fn read_text(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+    return decode(&bytes)
+        .map(|(text, _)| text)
+        .map_err(|_| Error::Binary { path: path.to_path_buf() });
+}
+
+pub fn decode(bytes: &[u8]) -> std::result::Result<(String, Encoding), &'static str> {
+    if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        return Ok((decode_utf16(rest, u16::from_le_bytes), Encoding::Utf16Le));
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        return Ok((decode_utf16(rest, u16::from_be_bytes), Encoding::Utf16Be));
+    }
+
+    if is_binary(bytes) {
+        return Err("looks like a binary file");
+    }
+
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    return match std::str::from_utf8(bytes) {
+        Ok(text) => Ok((text.to_owned(), Encoding::Utf8)),
+        Err(_) => Ok((bytes.iter().map(|b| *b as char).collect(), Encoding::Latin1)),
+    };
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    return char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+}
+
+/// A NUL byte, or more than one in ten bytes being a control character other
+/// than whitespace, backspace or escape, in the first `SNIFF_LENGTH` bytes.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(SNIFF_LENGTH)];
+    if sample.contains(&0) {
+        return true;
+    }
+
+    let control = sample.iter()
+        .filter(|b| b.is_ascii_control() && !matches!(b, b'\t' | b'\n' | b'\r' | b'\x0c' | b'\x08' | b'\x1b'))
+        .count();
+    return control * 10 > sample.len();
+}
@@ -0,0 +1,52 @@
+use std::{fmt, io, path::{Path, PathBuf}};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading `path` failed.
+    Io { path: PathBuf, source: io::Error },
+    /// `path` was read but its contents aren't text we can decode.
+    Decode { path: PathBuf, message: String },
+    /// `path` looks like a binary file, so isn't text at all.
+    Binary { path: PathBuf },
+    /// A query, regex or JSON document didn't parse.
+    Parse(String),
+    /// Encoded index data is truncated, corrupt or of another version.
+    IndexFormat(String),
+    /// The command line doesn't make sense.
+    Usage(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Wraps an I/O error with the path it happened on. `InvalidData`, which
+    /// `read_to_string` returns for bad UTF-8, becomes a decode error.
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        if source.kind() == io::ErrorKind::InvalidData {
+            return Error::Decode { path: path.to_path_buf(), message: source.to_string() };
+        }
+        return Error::Io { path: path.to_path_buf(), source };
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Decode { path, message } => write!(f, "{}: cannot decode: {}", path.display(), message),
+            Error::Binary { path } => write!(f, "{}: looks like a binary file", path.display()),
+            Error::Parse(message) => write!(f, "parse error: {}", message),
+            Error::IndexFormat(message) => write!(f, "invalid index data: {}", message),
+            Error::Usage(message) => write!(f, "{}", message),
+        };
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        };
+    }
+}
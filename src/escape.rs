@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+
+/// Decodes the JSON/JS/Rust-style escape at the start of `s`: `\n` and
+/// friends, `\x41`, `\u00e9`, UTF-16 surrogate pairs like `\ud83d\ude00`,
+/// and `\u{1F600}`. Returns the char and the length of the escape in bytes,
+/// or `None` if `s` doesn't start with a known escape.
+pub fn escape_at(s: &str) -> Option<(char, usize)> {
+    let rest = s.strip_prefix('\\')?;
+    let simple = match rest.chars().next()? {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        'b' => '\u{8}',
+        'f' => '\u{c}',
+        'v' => '\u{b}',
+        c @ ('\\' | '"' | '\'' | '/') => c,
+        'x' => return char::from_u32(hex(rest, 1, 2)?).map(|c| (c, 4)),
+        'u' => return unicode_escape(s),
+        _ => return None,
+    };
+    return Some((simple, 2));
+}
+
+fn unicode_escape(s: &str) -> Option<(char, usize)> {
+    if let Some(braced) = s.strip_prefix("\\u{") {
+        let close = braced.find('}')?;
+        if close == 0 || close > 6 {
+            return None;
+        }
+        let value = hex(braced, 0, close)?;
+        return Some((char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER), close + 4));
+    }
+
+    let value = hex(s, 2, 4)?;
+    return match value {
+        0xD800..=0xDBFF => {
+            let low = s.get(6..).filter(|r| r.starts_with("\\u")).and_then(|r| hex(r, 2, 4));
+            match low {
+                Some(low @ 0xDC00..=0xDFFF) => {
+                    let c = 0x10000 + ((value - 0xD800) << 10) + (low - 0xDC00);
+                    Some((char::from_u32(c)?, 12))
+                }
+                _ => Some((char::REPLACEMENT_CHARACTER, 6)),
+            }
+        }
+        0xDC00..=0xDFFF => Some((char::REPLACEMENT_CHARACTER, 6)),
+        _ => Some((char::from_u32(value)?, 6)),
+    };
+}
+
+fn hex(s: &str, from: usize, len: usize) -> Option<u32> {
+    let digits = s.get(from..from + len)?;
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    return u32::from_str_radix(digits, 16).ok();
+}
+
+/// The char at the start of `s`, decoding an escape if there is one.
+pub fn decode_char(s: &str) -> Option<(char, usize)> {
+    return escape_at(s).or_else(|| s.chars().next().map(|c| (c, c.len_utf8())));
+}
+
+/// Decodes every escape in `s`, borrowing when there is none. A backslash
+/// that doesn't start a known escape is kept as is.
+pub fn unescape(s: &str) -> Cow<'_, str> {
+    if !s.contains('\\') {
+        return Cow::Borrowed(s);
+    }
+
+    let mut output = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(at) = rest.find('\\') {
+        output.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let (c, len) = escape_at(rest).unwrap_or(('\\', 1));
+        output.push(c);
+        rest = &rest[len..];
+    }
+    output.push_str(rest);
+
+    return Cow::Owned(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::Analyzers;
+    use std::path::Path;
+
+    /// Every char of `s` decoded, with the source bytes it came from.
+    fn chars(s: &str) -> Vec<(char, &str)> {
+        let mut chars = Vec::new();
+        let mut at = 0;
+        while let Some((c, len)) = decode_char(&s[at..]) {
+            chars.push((c, &s[at..at + len]));
+            at += len;
+        }
+        return chars;
+    }
+
+    #[test]
+    fn escape_lengths_slice_the_source() {
+        assert_eq!(chars(r"a\n\x41\u00e9\u{1F600}\ud83d\ude00\q"), [
+            ('a', "a"),
+            ('\n', r"\n"),
+            ('A', r"\x41"),
+            ('é', r"\u00e9"),
+            ('😀', r"\u{1F600}"),
+            ('😀', r"\ud83d\ude00"),
+            ('\\', r"\"),
+            ('q', "q"),
+        ]);
+        assert_eq!(chars(r"\ud83dx"), [('\u{FFFD}', r"\ud83d"), ('x', "x")]);
+        assert_eq!(chars(r"\u00"), [('\\', r"\"), ('u', "u"), ('0', "0"), ('0', "0")]);
+    }
+
+    #[test]
+    fn unescape_decodes_known_escapes_only() {
+        assert!(matches!(unescape("plain"), Cow::Borrowed("plain")));
+        assert_eq!(unescape(r#"caf\u00e9 \"q\" C:\dir"#), "café \"q\" C:\\dir");
+    }
+
+    #[test]
+    fn json_term_spans_cover_the_escapes() {
+        let source = r#"{"name": "caf\u00e9 \"cr\u00e8me\"", "k\u0065y": 12}"#;
+        let terms = Analyzers::new().for_path(Path::new("x.json")).analyze(source).into_iter()
+            .filter(|term| term.text.chars().any(char::is_alphanumeric))
+            .map(|term| (term.text.into_owned(), &source[term.start..term.end]))
+            .collect::<Vec<_>>();
+        assert_eq!(terms, [
+            ("name".to_owned(), "name"),
+            ("café".to_owned(), r"caf\u00e9"),
+            ("crème".to_owned(), r"cr\u00e8me"),
+            ("key".to_owned(), r"k\u0065y"),
+            ("12".to_owned(), "12"),
+        ]);
+    }
+}
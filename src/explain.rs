@@ -0,0 +1,105 @@
+use std::{fmt, path::PathBuf};
+
+use crate::parse::JsonValue;
+
+/// How a document's score for a query came about: the score is the sum of
+/// the parts' scores, each the sum of its factors.
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub path: PathBuf,
+    pub parts: Vec<PartExplanation>,
+    pub score: f64,
+}
+
+/// One piece of the query, like a term, `title:x`, `*wild*` or `/regex/`.
+#[derive(Debug, Clone)]
+pub struct PartExplanation {
+    pub source: String,
+    pub kind: &'static str,
+    pub factors: Vec<Factor>,
+    pub score: f64,
+}
+
+/// `tf * idf` of one matched term, or of a regex or range.
+#[derive(Debug, Clone)]
+pub struct Factor {
+    pub term: String,
+    /// Occurrences in the document, or regex matches.
+    pub count: usize,
+    pub tf: f64,
+    /// Documents matching at all, out of `documents`.
+    pub df: usize,
+    pub documents: usize,
+    pub idf: f64,
+    pub score: f64,
+}
+
+impl Factor {
+    pub fn new(term: &str, count: usize, tf: f64, df: usize, documents: usize, idf: f64) -> Self {
+        return Self { term: term.to_owned(), count, tf, df, documents, idf, score: tf * idf };
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        return JsonValue::object(vec![
+            ("term", JsonValue::string(&self.term)),
+            ("count", JsonValue::IntegerValue(self.count as i64)),
+            ("tf", JsonValue::DecimalValue(self.tf)),
+            ("df", JsonValue::IntegerValue(self.df as i64)),
+            ("documents", JsonValue::IntegerValue(self.documents as i64)),
+            ("idf", JsonValue::DecimalValue(self.idf)),
+            ("score", JsonValue::DecimalValue(self.score)),
+        ]);
+    }
+}
+
+impl PartExplanation {
+    pub fn new(source: &str, kind: &'static str, factors: Vec<Factor>) -> Self {
+        let score = factors.iter().fold(0.0, |sum, f| sum + f.score);
+        return Self { source: source.to_owned(), kind, factors, score };
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        return JsonValue::object(vec![
+            ("source", JsonValue::string(&self.source)),
+            ("kind", JsonValue::string(self.kind)),
+            ("factors", JsonValue::ArrayValue(self.factors.iter().map(Factor::to_json).collect())),
+            ("score", JsonValue::DecimalValue(self.score)),
+        ]);
+    }
+}
+
+impl Explanation {
+    pub fn new(path: PathBuf, parts: Vec<PartExplanation>) -> Self {
+        let score = parts.iter().fold(0.0, |sum, p| sum + p.score);
+        return Self { path, parts, score };
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        return JsonValue::object(vec![
+            ("path", JsonValue::string(&self.path.display().to_string())),
+            ("parts", JsonValue::ArrayValue(self.parts.iter().map(PartExplanation::to_json).collect())),
+            ("score", JsonValue::DecimalValue(self.score)),
+        ]);
+    }
+}
+
+/// An indented tree, one line per part and per factor.
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:.4} = sum of {} part(s) for {}", self.score, self.parts.len(), self.path.display())?;
+        for part in self.parts.iter() {
+            writeln!(f, "  {:.4} {} ({})", part.score, part.source, part.kind)?;
+            if part.factors.is_empty() {
+                writeln!(f, "    no match")?;
+            }
+            for factor in part.factors.iter() {
+                writeln!(
+                    f,
+                    "    {:.4} {}: tf {:.4} (count {}) * idf {:.4} (in {} of {} documents)",
+                    factor.score, factor.term, factor.tf, factor.count, factor.idf, factor.df, factor.documents,
+                )?;
+            }
+        }
+        return Ok(());
+    }
+}
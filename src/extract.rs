@@ -0,0 +1,520 @@
+use std::path::Path;
+
+/// Field holding the document title: `<title>`, or the first top level heading.
+pub const TITLE_FIELD: &str = "title";
+/// Field holding the text of every heading.
+pub const HEADING_FIELD: &str = "heading";
+
+pub const FIELDS: &[&str] = &[TITLE_FIELD, HEADING_FIELD];
+
+/// Most bytes from a `&` to the `;` of an entity we decode, as in `&#x10FFFF;`.
+const MAX_ENTITY: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Html,
+    Xml,
+}
+
+impl Format {
+    pub fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        return match extension.as_str() {
+            "md" | "markdown" => Some(Format::Markdown),
+            "html" | "htm" | "xhtml" => Some(Format::Html),
+            "xml" | "svg" | "rss" | "atom" | "xsd" | "xsl" => Some(Format::Xml),
+            _ => None,
+        };
+    }
+}
+
+/// The visible text of a document. Markup is blanked out with spaces and
+/// entities are decoded in place, padded with invisible format chars (see
+/// `is_padding`) that don't break words, so `text` keeps the byte offsets of
+/// the source and spans over it still point into the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extracted {
+    pub text: String,
+    pub title: Option<String>,
+    /// `(field, text)` pairs, like every heading under `HEADING_FIELD`.
+    pub fields: Vec<(&'static str, String)>,
+}
+
+struct Extractor<'s> {
+    source: &'s str,
+    visible: Vec<u8>,
+    /// Byte ranges of headings, with their level.
+    headings: Vec<(usize, usize, u8)>,
+    title: Option<(usize, usize)>,
+    /// Byte ranges of fenced code blocks in Markdown, in order. Tags and
+    /// entities in them are text.
+    code: Vec<(usize, usize)>,
+}
+
+pub fn extract(format: Format, source: &str) -> Extracted {
+    let mut extractor = Extractor {
+        source,
+        visible: source.as_bytes().to_vec(),
+        headings: Vec::new(),
+        title: None,
+        code: Vec::new(),
+    };
+
+    if format == Format::Markdown {
+        extractor.markdown();
+    }
+    extractor.tags(format != Format::Xml);
+    extractor.entities();
+
+    return extractor.finish();
+}
+
+impl Extractor<'_> {
+
+    fn blank(&mut self, start: usize, end: usize) {
+        for b in &mut self.visible[start..end.min(self.source.len())] {
+            if *b != b'\n' {
+                *b = b' ';
+            }
+        }
+    }
+
+    /// End of the code block `at` is in, if any.
+    fn code_end(&self, at: usize) -> Option<usize> {
+        let next = self.code.partition_point(|(start, _)| *start <= at);
+        return self.code[..next].last().filter(|(_, end)| at < *end).map(|(_, end)| *end);
+    }
+
+    fn markdown(&mut self) {
+        let source = self.source;
+        let mut fence: Option<(&str, usize)> = None;
+        let mut previous: Option<(usize, usize)> = None;
+        let mut offset = 0;
+
+        for line in source.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let content = line.trim_end_matches(['\n', '\r']);
+            let end = start + content.len();
+            let trimmed = content.trim_start();
+            let indent = content.len() - trimmed.len();
+
+            let marker = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m));
+            if let Some(marker) = marker.filter(|m| fence.map(|(f, _)| f == *m).unwrap_or(true)) {
+                fence = match fence {
+                    Some((_, code_start)) => {
+                        self.code.push((code_start, start));
+                        None
+                    }
+                    None => Some((marker, offset)),
+                };
+                self.blank(start, end);
+                previous = None;
+                continue;
+            }
+            if fence.is_some() {
+                continue;
+            }
+
+            if trimmed.is_empty() {
+                previous = None;
+                continue;
+            }
+
+            // setext headings are underlined with `===` or `---`
+            let underline = trimmed.trim_end();
+            if let Some((p_start, p_end)) = previous {
+                if underline.bytes().all(|b| b == b'=') || underline.bytes().all(|b| b == b'-') {
+                    let level = if underline.starts_with('=') { 1 } else { 2 };
+                    self.headings.push((p_start, p_end, level));
+                    self.blank(start, end);
+                    previous = None;
+                    continue;
+                }
+            }
+
+            if is_rule(trimmed) || is_link_definition(trimmed) {
+                self.blank(start, end);
+                previous = None;
+                continue;
+            }
+
+            let mut at = start + indent;
+            let hashes = trimmed.bytes().take_while(|b| *b == b'#').count();
+            if (1..=6).contains(&hashes) && trimmed[hashes..].chars().next().map(|c| c == ' ' || c == '\t').unwrap_or(true) {
+                let text = trimmed[hashes..].trim_end().trim_end_matches('#');
+                self.blank(at, at + hashes);
+                self.blank(at + hashes + text.len(), end);
+                self.headings.push((at + hashes, at + hashes + text.len(), hashes as u8));
+                self.inline(at + hashes, end);
+                previous = None;
+                continue;
+            }
+
+            at += self.block_markers(at, end);
+            self.inline(at, end);
+            previous = Some((at, end));
+        }
+
+        if let Some((_, code_start)) = fence {
+            self.code.push((code_start, source.len()));
+        }
+    }
+
+    /// Blanks blockquote `>`s, list bullets, numbers and task boxes at the
+    /// start of a line and returns how many bytes they took.
+    fn block_markers(&mut self, start: usize, end: usize) -> usize {
+        let line = &self.source[start..end];
+        let mut at = 0;
+
+        loop {
+            let rest = &line[at..];
+            let space = rest.len() - rest.trim_start().len();
+            let rest = &rest[space..];
+
+            let digits = rest.bytes().take_while(|b| b.is_ascii_digit()).count();
+            let bullet = rest.starts_with(['-', '*', '+']) && rest[1..].starts_with([' ', '\t']);
+            let marker = if rest.starts_with('>') || bullet {
+                1
+            } else if digits > 0 && rest[digits..].starts_with(['.', ')']) && rest[digits + 1..].starts_with([' ', '\t']) {
+                digits + 1
+            } else if at > 0 && ["[ ] ", "[x] ", "[X] "].iter().any(|t| rest.starts_with(t)) {
+                3
+            } else {
+                return at;
+            };
+
+            self.blank(start + at, start + at + space + marker);
+            at += space + marker;
+        }
+    }
+
+    /// Blanks inline markup in `[start, end)`: code ticks, emphasis, the
+    /// brackets of links and images and their targets, table pipes and
+    /// backslashes of escapes.
+    fn inline(&mut self, start: usize, end: usize) {
+        let line = &self.source[start..end];
+        let bytes = line.as_bytes();
+        let table_separator = line.contains('|') && line.bytes().all(|b| b"|-: \t".contains(&b));
+        if table_separator {
+            self.blank(start, end);
+            return;
+        }
+
+        let mut i = 0;
+        while i < bytes.len() {
+            let at = start + i;
+            match bytes[i] {
+                b'\\' if bytes.get(i + 1).map(|b| b.is_ascii_punctuation()).unwrap_or(false) => {
+                    self.blank(at, at + 1);
+                    i += 2;
+                    continue;
+                }
+                b'`' | b'*' | b'~' | b'|' | b'[' => self.blank(at, at + 1),
+                b'!' if bytes.get(i + 1) == Some(&b'[') => self.blank(at, at + 1),
+                b'_' => {
+                    let before = line[..i].chars().next_back().map(|c| c.is_alphanumeric()).unwrap_or(false);
+                    let after = line[i + 1..].chars().next().map(|c| c.is_alphanumeric()).unwrap_or(false);
+                    if !(before && after) {
+                        self.blank(at, at + 1);
+                    }
+                }
+                b']' => {
+                    let close = match bytes.get(i + 1) {
+                        Some(b'(') => line[i..].find(')'),
+                        Some(b'[') => line[i + 1..].find(']').map(|n| n + 1),
+                        _ => Some(0),
+                    };
+                    let close = close.map(|n| i + n + 1).unwrap_or(i + 1);
+                    self.blank(at, start + close);
+                    i = close;
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// Blanks tags, comments and processing instructions. CDATA content
+    /// stays visible; in HTML `<script>` and `<style>` content doesn't.
+    fn tags(&mut self, html: bool) {
+        let source = self.source;
+        let lower = source.to_ascii_lowercase();
+        let mut open: Vec<(String, usize)> = Vec::new();
+        let mut i = 0;
+
+        while let Some(n) = source[i..].find('<') {
+            let at = i + n;
+            if let Some(end) = self.code_end(at) {
+                i = end;
+                continue;
+            }
+            let rest = &source[at..];
+
+            if rest.starts_with("<!--") {
+                let end = rest.find("-->").map(|n| at + n + 3).unwrap_or(source.len());
+                self.blank(at, end);
+                i = end;
+                continue;
+            }
+            if rest.starts_with("<![CDATA[") {
+                let close = rest.find("]]>").map(|n| at + n).unwrap_or(source.len());
+                self.blank(at, at + 9);
+                self.blank(close, close + 3);
+                i = (close + 3).min(source.len());
+                continue;
+            }
+
+            let opens_tag = rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!' || c == '?');
+            let end = match tag_end(rest) {
+                Some(end) if opens_tag => at + end,
+                _ => {
+                    i = at + 1;
+                    continue;
+                }
+            };
+            self.blank(at, end);
+            i = end;
+
+            let closing = rest.starts_with("</");
+            let name = lower[at..end].trim_start_matches(['<', '/'])
+                .split(|c: char| !(c.is_ascii_alphanumeric() || c == ':' || c == '-'))
+                .next()
+                .unwrap_or("")
+                .to_owned();
+
+            if html && !closing && (name == "script" || name == "style") {
+                let close = lower[end..].find(&format!("</{}", name)).map(|n| end + n).unwrap_or(source.len());
+                self.blank(end, close);
+                i = close;
+                continue;
+            }
+
+            let level = match name.as_str() {
+                "title" => 0,
+                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => name.as_bytes()[1] - b'0',
+                _ => continue,
+            };
+            if !closing && !lower[at..end].ends_with("/>") {
+                open.push((name, end));
+            } else if let Some(position) = open.iter().rposition(|(n, _)| *n == name) {
+                let (_, start) = open.remove(position);
+                if level == 0 {
+                    self.title.get_or_insert((start, at));
+                } else {
+                    self.headings.push((start, at, level));
+                }
+            }
+        }
+    }
+
+    /// Decodes `&amp;`, `&#233;`, `&#xE9;` and the common named entities.
+    fn entities(&mut self) {
+        let source = self.source;
+        let mut i = 0;
+
+        while let Some(n) = source[i..].find('&') {
+            let at = i + n;
+            i = at + 1;
+            if let Some(end) = self.code_end(at) {
+                i = end;
+                continue;
+            }
+            if self.visible[at] != b'&' {
+                continue;
+            }
+
+            let window = &source.as_bytes()[at..(at + MAX_ENTITY + 1).min(source.len())];
+            let end = match window.iter().position(|b| *b == b';') {
+                Some(end) => at + end + 1,
+                None => continue,
+            };
+            let decoded = match decode_entity(&source[at + 1..end - 1]) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            let mut replacement = decoded.to_string();
+            let mut left = (end - at) - replacement.len();
+            while left > 1 {
+                let pad = if left == 2 || left == 4 { '\u{ad}' } else { '\u{2060}' };
+                replacement.push(pad);
+                left -= pad.len_utf8();
+            }
+            if left == 1 {
+                replacement.push(' ');
+            }
+            self.visible[at..end].copy_from_slice(replacement.as_bytes());
+            i = end;
+        }
+    }
+
+    fn finish(self) -> Extracted {
+        // every edit replaces whole chars with whole chars, so this is lossless
+        let text = String::from_utf8_lossy(&self.visible).into_owned();
+
+        let mut fields = Vec::new();
+        let mut title = self.title.map(|(s, e)| collapse(&text[s..e]));
+        for &(start, end, _) in &self.headings {
+            let heading = collapse(&text[start..end]);
+            if !heading.is_empty() {
+                fields.push((HEADING_FIELD, heading));
+            }
+        }
+        if title.as_deref().map(str::is_empty).unwrap_or(true) {
+            let top = self.headings.iter().map(|h| h.2).min();
+            title = self.headings.iter()
+                .filter(|h| Some(h.2) == top)
+                .map(|&(s, e, _)| collapse(&text[s..e]))
+                .find(|t| !t.is_empty());
+        }
+        if let Some(title) = &title {
+            fields.push((TITLE_FIELD, title.clone()));
+        }
+
+        return Extracted { text, title, fields };
+    }
+}
+
+/// End of the tag starting at the beginning of `rest`, skipping `>` inside
+/// quoted attribute values.
+fn tag_end(rest: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in rest.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(i + 1),
+            (None, '<') => return None,
+            _ => {}
+        }
+    }
+    return None;
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let value = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(value);
+    }
+
+    return Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        _ => return None,
+    });
+}
+
+fn is_rule(line: &str) -> bool {
+    let line = line.trim_end();
+    let first = match line.chars().next() {
+        Some(c @ ('-' | '*' | '_')) => c,
+        _ => return false,
+    };
+    return line.chars().filter(|c| *c == first).count() >= 3
+        && line.chars().all(|c| c == first || c == ' ');
+}
+
+/// `[label]: target` lines, which never show up in the rendered text.
+fn is_link_definition(line: &str) -> bool {
+    return line.starts_with('[')
+        && line.find("]:").map(|n| n > 1 && !line[1..n].contains(']')).unwrap_or(false);
+}
+
+/// Soft hyphens and word joiners stand in for the bytes decoded entities
+/// no longer take. Both are ignored by word segmentation.
+pub fn is_padding(c: char) -> bool {
+    return c == '\u{ad}' || c == '\u{2060}';
+}
+
+fn collapse(text: &str) -> String {
+    let text = text.replace(is_padding, "");
+    return text.split_whitespace().collect::<Vec<_>>().join(" ");
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::Analyzers;
+
+    /// Terms of the extracted text of `source`, each with the source bytes
+    /// its span covers.
+    fn spans<'s>(name: &str, source: &'s str) -> Vec<(String, &'s str)> {
+        let path = Path::new(name);
+        let extracted = extract(Format::of(path).unwrap(), source);
+        assert_eq!(extracted.text.len(), source.len());
+        let lines = |text: &str| text.match_indices('\n').map(|(i, _)| i).collect::<Vec<_>>();
+        assert_eq!(lines(&extracted.text), lines(source));
+
+        return Analyzers::new().for_path(path).analyze(&extracted.text).into_iter()
+            .map(|term| (term.text.into_owned(), &source[term.start..term.end]))
+            .collect();
+    }
+
+    #[test]
+    fn html_offsets_point_into_the_source() {
+        let source = "<html><head><title>Caf&#233; &amp; Bar</title></head>\n\
+            <body><p>Hello <b>w&#246;rld</b> &lt;tag&gt; &#x263A;&#233;t&#xE9;</p></body></html>";
+        assert_eq!(spans("x.html", source), [
+            ("café".to_owned(), "Caf&#233;"),
+            ("bar".to_owned(), "Bar"),
+            ("hello".to_owned(), "Hello"),
+            ("wörld".to_owned(), "w&#246;rld"),
+            ("tag".to_owned(), "tag"),
+            ("été".to_owned(), "&#233;t&#xE9;"),
+        ]);
+    }
+
+    #[test]
+    fn markdown_offsets_point_into_the_source() {
+        let source = "# Caf&#233; *bold*\n\nSome [link](http://x.y) and `code` &copy; x&gt;y\n\n```\nfenced\n```\n";
+        assert_eq!(spans("x.md", source), [
+            ("café".to_owned(), "Caf&#233;"),
+            ("bold".to_owned(), "bold"),
+            ("some".to_owned(), "Some"),
+            ("link".to_owned(), "link"),
+            ("and".to_owned(), "and"),
+            ("code".to_owned(), "code"),
+            ("x".to_owned(), "x"),
+            ("y".to_owned(), "y"),
+            ("fenced".to_owned(), "fenced"),
+        ]);
+    }
+
+    #[test]
+    fn fenced_code_keeps_tags_and_entities() {
+        let source = "<b>bold</b>\n```html\n<p>a &amp; b</p>\n```\n<i>x</i> &amp;\n~~~\n<open\n";
+        let extracted = extract(Format::Markdown, source);
+        assert_eq!(extracted.text, "   bold    \n       \n<p>a &amp; b</p>\n   \n   x     &\u{ad}\u{ad}\n   \n<open\n");
+    }
+
+    #[test]
+    fn bare_ampersands_are_left_alone() {
+        let source = "a & b &amp c &#12345678; &bogus; &#x41;".to_owned() + &" &".repeat(100_000);
+        let extracted = extract(Format::Html, &source);
+        assert!(extracted.text.starts_with("a & b &amp c &#12345678; &bogus; A\u{2060}\u{ad} &"));
+        assert_eq!(extracted.text.len(), source.len());
+    }
+}
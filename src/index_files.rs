@@ -1,131 +1,572 @@
 use std::{
     path::{Path, PathBuf},
-    fs::{DirEntry, File},
-    io::{BufReader, Read},
-    collections::HashMap
+    fs::{DirEntry, ReadDir},
+    collections::HashMap,
+    fmt,
+    time::Instant,
 };
 
-use crate::{Lexer, trie::Triee};
-
-pub fn index(path: &Path, join_files: bool, file_extension: &str)
-    -> Result<(Index, Triee), std::io::Error> {
-
-    let mut triee = Triee::new();
-    return index_folder_content(&mut triee, path, join_files, file_extension)
-        .map(|r|
-            (
-                r.into_iter()
-                .collect::<HashMap<PathBuf, Document>>(),
-                triee
-            )
-        )
-}
-
-fn index_folder_content(triee: &mut Triee, path: &Path, join_files: bool, file_extension: &str)
-    -> std::io::Result<Vec<(PathBuf, HashMap<String, usize>)>> {
-
-    let entries = path.read_dir()
-        .expect("Failed to open provided path")
-        .filter_map(|entry| entry.ok())
-        .collect::<Vec<_>>();
-
-    let files = entries.iter()
-        .filter_map(|en| en.metadata()
-            .ok()
-            .and_then(|s|
-                if s.is_file()
-                    { Some(en) }
-                else
-                    { None }
-            )
-        ).collect::<Vec<_>>();
-
-    let dirs = entries.iter()
-        .filter_map(|en| en.metadata()
-            .ok()
-            .and_then(|s|
-                if s.is_dir()
-                    { Some(en) }
-                else
-                    { None }
-            )
-        ).collect::<Vec<_>>();
-
-    let files_ind =  index_files(triee, files, path.to_path_buf(), join_files, file_extension)?;
-    let dirs_ind = index_folders(triee, dirs, join_files, file_extension)?;
-
-    return Ok(files_ind.into_iter().chain(dirs_ind.into_iter()).collect());
-}
-
-pub type Index = HashMap<PathBuf, HashMap<String, usize>>;
+use crate::{
+    analyzer::{Analyzer, Analyzers},
+    binary::{self, Reader},
+    lexer::TokenKind,
+    trie::Triee,
+    documents::{DocId, DocumentRegistry, FileInfo},
+    encoding,
+    error::{Error, Result},
+    escape,
+    ngram::NgramIndex,
+    numeric::NumericIndex,
+    extract::{self, Format},
+    log::{self, debug, info, verbose},
+    progress::{has_extension, Progress},
+};
+
+pub type Index = HashMap<DocId, Document>;
 pub type Document = HashMap<String, usize>;
 
-fn index_folders(triee: &mut Triee, folder_entries: Vec<&DirEntry>, join_files: bool, file_extension: &str) -> std::io::Result<Vec<(PathBuf, HashMap<String, usize>)>> {
-    return Ok(
-        folder_entries.iter()
-            .map(|f| index_folder_content(triee, &f.path(), join_files, file_extension))
-            .filter(|r| r.is_ok())
-            .flat_map(|r| r.unwrap().into_iter())
-            .collect()
-    )
+const CORPUS_MAGIC: &[u8] = b"FJIX\x06";
 
+#[derive(Debug, Clone)]
+pub struct IndexOptions {
+    pub granularity: Granularity,
+    /// Extensions of the files to index, without the dot.
+    pub extensions: Vec<String>,
+    pub ngrams: bool,
+    /// How files are turned into terms, by extension.
+    pub analyzers: Analyzers,
+    pub on_error: ErrorPolicy,
 }
 
-fn index_files(triee: &mut Triee, file_entreis: Vec<&DirEntry>, path: PathBuf, join_files: bool, file_extension: &str) -> std::io::Result<Vec<(PathBuf, HashMap<String, usize>)>> {
-    if join_files {
-        let mut collector = HashMap::new();
+/// What makes up one document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Granularity {
+    /// Every file is a document.
+    #[default]
+    File,
+    /// The matching files directly in a folder are one document, keyed by
+    /// the folder.
+    Directory,
+    /// Everything under the same first `n` folders below the root is one
+    /// document; files higher up go to their own folder. `Depth(1)` groups
+    /// by top-level folder.
+    Depth(usize),
+}
+
+impl Granularity {
+    /// `file`, `dir`, `top` or a depth.
+    pub fn parse(name: &str) -> Option<Self> {
+        return match name {
+            "file" => Some(Granularity::File),
+            "dir" | "directory" => Some(Granularity::Directory),
+            "top" => Some(Granularity::Depth(1)),
+            depth => depth.parse().ok().filter(|d| *d > 0).map(Granularity::Depth),
+        };
+    }
+
+    /// The document `file`, found under `root`, belongs to.
+    pub fn document_path(&self, root: &Path, file: &Path) -> PathBuf {
+        let folder = file.parent().unwrap_or(root);
+        return match self {
+            Granularity::File => file.to_path_buf(),
+            Granularity::Directory => folder.to_path_buf(),
+            Granularity::Depth(n) => match folder.strip_prefix(root) {
+                Ok(relative) if relative.as_os_str().is_empty() => root.to_path_buf(),
+                Ok(relative) => root.join(relative.components().take(*n).collect::<PathBuf>()),
+                Err(_) => folder.to_path_buf(),
+            },
+        };
+    }
 
-        for entry in file_entreis {
-            index_single_file(triee, entry, &mut collector, file_extension)?;
+    fn code(&self) -> u64 {
+        return match self {
+            Granularity::File => 0,
+            Granularity::Directory => 1,
+            Granularity::Depth(n) => *n as u64 + 1,
+        };
+    }
+
+    fn from_code(code: u64) -> Self {
+        return match code {
+            0 => Granularity::File,
+            1 => Granularity::Directory,
+            n => Granularity::Depth(n as usize - 1),
+        };
+    }
+}
+
+impl fmt::Display for Granularity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Granularity::File => write!(f, "file"),
+            Granularity::Directory => write!(f, "dir"),
+            Granularity::Depth(1) => write!(f, "top"),
+            Granularity::Depth(n) => write!(f, "{}", n),
+        };
+    }
+}
 
+/// What indexing does with a file or folder it can't read or decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Leave it out and report it in `Corpus::skipped`.
+    #[default]
+    Skip,
+    /// Stop indexing and return the error.
+    FailFast,
+}
+
+impl IndexOptions {
+    pub fn new(extensions: &[&str]) -> Self {
+        return Self {
+            granularity: Granularity::default(),
+            extensions: extensions.iter().map(|e| e.to_string()).collect(),
+            ngrams: false,
+            analyzers: Analyzers::new(),
+            on_error: ErrorPolicy::default(),
+        };
+    }
+}
+
+pub struct Corpus {
+    pub options: IndexOptions,
+    pub documents: DocumentRegistry,
+    pub index: Index,
+    pub triee: Triee,
+    pub ngrams: Option<NgramIndex>,
+    pub numbers: NumericIndex,
+    /// Term index per extracted field, like headings and titles.
+    pub fields: HashMap<String, Index>,
+    /// Files and folders left out under `ErrorPolicy::Skip`, and binary
+    /// files under either policy, with why.
+    pub skipped: Vec<Error>,
+}
+
+#[allow(dead_code)]
+impl Corpus {
+    pub fn new(options: IndexOptions) -> Self {
+        return Self {
+            ngrams: if options.ngrams { Some(NgramIndex::new()) } else { None },
+            options,
+            documents: DocumentRegistry::new(),
+            index: Index::new(),
+            triee: Triee::new(),
+            numbers: NumericIndex::new(),
+            fields: HashMap::new(),
+            skipped: Vec::new(),
+        };
+    }
+
+    /// Drops a document from the registry, the term index and the triee.
+    pub fn remove_document(&mut self, id: DocId) -> Option<Document> {
+        self.documents.remove(id)?;
+        self.triee.remove_path(id);
+        self.numbers.remove_doc(id);
+        if let Some(ngrams) = self.ngrams.as_mut() {
+            ngrams.remove_doc(id);
         }
-        return Ok(vec![(path, collector)]);
+        for field in self.fields.values_mut() {
+            field.remove(&id);
+        }
+        return self.index.remove(&id);
     }
 
-    let mut res = Vec::new();
-    for entry in file_entreis {
+    /// Re-reads a document from disk, keeping its id. Grouped documents are
+    /// rebuilt from the files they were made of. If one of them can't be
+    /// read the document is removed, rather than left half indexed.
+    pub fn update_document(&mut self, id: DocId) -> Result<()> {
+        let files = match self.documents.get_mut(id) {
+            Some(info) => {
+                info.size = 0;
+                info.modified = None;
+                info.length = 0;
+                info.title = None;
+                std::mem::take(&mut info.files)
+            }
+            None => return Ok(()),
+        };
+
+        self.triee.remove_path(id);
+        self.numbers.remove_doc(id);
+        if let Some(ngrams) = self.ngrams.as_mut() {
+            ngrams.remove_doc(id);
+        }
+        for field in self.fields.values_mut() {
+            field.remove(&id);
+        }
         let mut collector = HashMap::new();
-        index_single_file(triee, entry, &mut collector, file_extension)?;
-        res.push((entry.path(), collector))
+
+        for file in files {
+            if let Err(e) = index_single_file(self, &file.path, id, &mut collector) {
+                self.remove_document(id);
+                return Err(e);
+            }
+        }
+
+        self.index.insert(id, collector);
+        return Ok(());
     }
 
-    Ok(res)
-}
+    /// Saves the corpus. Analyzers and the error policy aren't saved, they
+    /// come from the options given to `load`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut out = CORPUS_MAGIC.to_vec();
+        binary::write_varint(&mut out, self.options.extensions.len() as u64);
+        for extension in self.options.extensions.iter() {
+            binary::write_str(&mut out, extension);
+        }
+        binary::write_varint(&mut out, self.options.granularity.code());
 
-fn index_single_file(triee: &mut Triee, entry: &DirEntry, collector: &mut Document, file_extension: &str) -> std::io::Result<()> {
-    if entry.path().extension().map(|e| e != file_extension).unwrap_or(true) {
+        self.documents.encode(&mut out);
+        encode_index(&self.index, &mut out);
+        binary::write_varint(&mut out, self.fields.len() as u64);
+        for (field, index) in self.fields.iter() {
+            binary::write_str(&mut out, field);
+            encode_index(index, &mut out);
+        }
+        out.extend(self.triee.encode());
+        self.numbers.encode(&mut out);
+        binary::write_varint(&mut out, self.ngrams.is_some() as u64);
+        if let Some(ngrams) = self.ngrams.as_ref() {
+            ngrams.encode(&mut out);
+        }
+
+        return std::fs::write(path, out).map_err(|e| Error::io(path, e));
+    }
+
+    pub fn load(path: &Path, mut options: IndexOptions) -> Result<Self> {
+        let started = Instant::now();
+        let bytes = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+        let mut reader = Reader::new(&bytes);
+        if reader.bytes(CORPUS_MAGIC.len())? != CORPUS_MAGIC {
+            return Err(Error::IndexFormat(format!("{} is not a saved index", path.display())));
+        }
+
+        options.extensions = (0..reader.varint()?)
+            .map(|_| reader.str().map(str::to_owned))
+            .collect::<Result<_>>()?;
+        options.granularity = Granularity::from_code(reader.varint()?);
+
+        let documents = DocumentRegistry::decode_from(&mut reader)?;
+        let index = decode_index(&mut reader)?;
+        let mut fields = HashMap::new();
+        for _ in 0..reader.varint()? {
+            let field = reader.str()?.to_owned();
+            fields.insert(field, decode_index(&mut reader)?);
+        }
+        let triee = Triee::decode_from(&mut reader)?;
+        let numbers = NumericIndex::decode_from(&mut reader)?;
+        let ngrams = match reader.varint()? {
+            0 => None,
+            _ => Some(NgramIndex::decode_from(&mut reader)?),
+        };
+        options.ngrams = ngrams.is_some();
+        if !reader.is_empty() {
+            return Err(Error::IndexFormat("Trailing data after index".to_owned()));
+        }
+        debug!("Loaded {} documents from {} in {:?}", documents.len(), path.display(), started.elapsed());
+
+        return Ok(Self { options, documents, index, triee, ngrams, numbers, fields, skipped: Vec::new() });
+    }
+
+    /// Records an unreadable file or folder, or fails with it, by policy.
+    /// Binary files aren't failures, they are always just left out.
+    fn skip(&mut self, error: Error) -> Result<()> {
+        let binary = matches!(error, Error::Binary { .. });
+        if self.options.on_error == ErrorPolicy::FailFast && !binary {
+            return Err(error);
+        }
+        info!("skipped {}", error);
+        self.skipped.push(error);
         return Ok(());
     }
 
-    println!("Indexing {}", entry.path().display());
+    /// Visible text of a file, with markup blanked out for formats `extract`
+    /// knows, so offsets into it are offsets into the file.
+    pub fn visible_text(path: &Path, text: String) -> String {
+        return match Format::of(path) {
+            Some(format) => extract::extract(format, &text).text,
+            None => text,
+        };
+    }
 
-    let mut buffer = String::new();
-    {
-        let mut reader = BufReader::new(
-            File::open(entry.path())?
-        );
-        reader.read_to_string(&mut buffer)?;
+    /// What the analyzers see of a file, as one string: its visible text,
+    /// with escapes decoded if its analyzer decodes them. Trigrams and regexes
+    /// work on this, so they agree with the indexed terms. Offsets into the
+    /// file don't survive.
+    pub fn decoded_text(&self, path: &Path, text: String) -> String {
+        return decode(self.options.analyzers.for_path(path), &Self::visible_text(path, text));
     }
 
-    let binding = buffer.chars().collect::<Vec<_>>();
-    let lex = Lexer::new(&binding);
+    /// Current contents of the files making up a document.
+    pub fn read_document(&self, id: DocId) -> Result<Vec<(PathBuf, String)>> {
+        let files = match self.documents.get(id) {
+            Some(info) => &info.files,
+            None => return Ok(Vec::new()),
+        };
 
+        let mut contents = Vec::new();
+        for file in files {
+            let text = encoding::read_text(&file.path)?;
+            contents.push((file.path.clone(), text));
+        }
+        return Ok(contents);
+    }
+}
 
-    for token in lex {
-        // dbg!(token);
-        let value = String::from_iter(token);//.to_uppercase();
-        let up_token = value.chars().collect::<Vec<_>>();
+fn decode(analyzer: &Analyzer, visible: &str) -> String {
+    let text = visible.replace(extract::is_padding, "");
+    if !analyzer.decodes_escapes() {
+        return text;
+    }
+    return escape::unescape(&text).into_owned();
+}
 
-        triee.insert_word(&up_token, entry.path());
+fn encode_index(index: &Index, out: &mut Vec<u8>) {
+    binary::write_varint(out, index.len() as u64);
+    for (id, document) in index.iter() {
+        binary::write_varint(out, *id as u64);
+        binary::write_varint(out, document.len() as u64);
+        for (term, count) in document.iter() {
+            binary::write_str(out, term);
+            binary::write_varint(out, *count as u64);
+        }
+    }
+}
 
-        collector.insert(
-            value.clone(),
-            if collector.contains_key(&value)
-                { *collector.get(&value).unwrap() + 1 }
-            else
-                { 1 }
-        );
+fn decode_index(reader: &mut Reader) -> Result<Index> {
+    let mut index = Index::new();
+    for _ in 0..reader.varint()? {
+        let id = reader.varint()? as DocId;
+        let mut document = Document::new();
+        for _ in 0..reader.varint()? {
+            let term = reader.str()?.to_owned();
+            document.insert(term, reader.varint()? as usize);
+        }
+        index.insert(id, document);
+    }
+    return Ok(index);
+}
+
+pub fn index(path: &Path, options: IndexOptions) -> Result<Corpus> {
+    let mut corpus = Corpus::new(options);
+    // the root has to be readable whatever the policy
+    let entries = path.read_dir().map_err(|e| Error::io(path, e))?;
+    let mut progress = Progress::new(path, &corpus.options.extensions);
+    let result = index_folder_content(&mut corpus, &mut progress, path, path, entries);
+    log::status(None);
+    result?;
+    let binary = corpus.skipped.iter().filter(|e| matches!(e, Error::Binary { .. })).count();
+    progress.finish(binary, corpus.skipped.len() - binary);
+    return Ok(corpus);
+}
+
+fn index_folder_content(
+    corpus: &mut Corpus,
+    progress: &mut Progress,
+    root: &Path,
+    path: &Path,
+    read_dir: ReadDir,
+) -> Result<()> {
+    let mut entries = Vec::new();
+    for entry in read_dir {
+        match entry {
+            Ok(entry) => entries.push(entry),
+            Err(e) => corpus.skip(Error::io(path, e))?,
+        }
+    }
+
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for entry in entries.iter() {
+        match entry.metadata() {
+            Ok(m) if m.is_file() && has_extension(&entry.path(), &corpus.options.extensions) => files.push(entry),
+            Ok(m) if m.is_dir() => dirs.push(entry),
+            Ok(m) if m.is_file() => progress.ignored += 1,
+            Ok(_) => {}
+            Err(e) => corpus.skip(Error::io(&entry.path(), e))?,
+        }
+    }
+
+    files.sort_by_key(|entry| entry.path());
+    dirs.sort_by_key(|entry| entry.path());
+    index_files(corpus, progress, root, files)?;
+    index_folders(corpus, progress, root, dirs)?;
+
+    return Ok(());
+}
+
+fn index_folders(corpus: &mut Corpus, progress: &mut Progress, root: &Path, folder_entries: Vec<&DirEntry>) -> Result<()> {
+    for f in folder_entries {
+        let path = f.path();
+        match path.read_dir() {
+            Ok(entries) => index_folder_content(corpus, progress, root, &path, entries)?,
+            Err(e) => corpus.skip(Error::io(&path, e))?,
+        }
+    }
+    return Ok(());
+}
+
+/// Adds each file to the document its granularity puts it in. A document
+/// none of whose files could be read is dropped again.
+fn index_files(corpus: &mut Corpus, progress: &mut Progress, root: &Path, file_entreis: Vec<&DirEntry>) -> Result<()> {
+    for entry in file_entreis {
+        let path = entry.path();
+        let id = corpus.documents.register(corpus.options.granularity.document_path(root, &path));
+        let mut collector = corpus.index.remove(&id).unwrap_or_default();
+        let result = index_single_file(corpus, &path, id, &mut collector);
+        corpus.index.insert(id, collector);
+        progress.file_done(entry.metadata().map(|m| m.len()).unwrap_or(0), result.is_ok());
+
+        if let Err(e) = result {
+            if corpus.documents.get(id).map(|info| info.files.is_empty()).unwrap_or(false) {
+                corpus.remove_document(id);
+            }
+            corpus.skip(e)?;
+        }
     }
 
     Ok(())
 }
+
+/// Whatever can fail is done before anything is inserted, so a file that
+/// fails leaves nothing behind.
+fn index_single_file(corpus: &mut Corpus, path: &Path, id: DocId, collector: &mut Document) -> Result<()> {
+    verbose!("Indexing {}", path.display());
+    let started = Instant::now();
+
+    let buffer = encoding::read_text(path)?;
+    let metadata = path.metadata().map_err(|e| Error::io(path, e))?;
+    let extracted = Format::of(path).map(|format| extract::extract(format, &buffer));
+    let visible = extracted.as_ref().map(|e| e.text.as_str()).unwrap_or(&buffer);
+
+    let analyzer = corpus.options.analyzers.for_path(path);
+    if let Some(ngrams) = corpus.ngrams.as_mut() {
+        ngrams.insert_text(&decode(analyzer, visible), id);
+    }
+
+    let terms = analyzer.analyze(visible);
+
+    let mut length = 0;
+    // last word seen, as long as only quotes and `:`/`=` followed it
+    let mut key: Option<String> = None;
+    for term in terms {
+        match term.kind {
+            TokenKind::Number(_) | TokenKind::Date(_) => {
+                corpus.numbers.insert(key.as_deref(), term.kind, id);
+                key = None;
+            }
+            TokenKind::Word if term.sub_word => {}
+            TokenKind::Word => key = Some(term.text.to_string()),
+            TokenKind::Symbol if matches!(&*term.text, "\"" | "'" | ":" | "=") => {}
+            TokenKind::Symbol => key = None,
+        }
+
+        corpus.triee.insert_word(&term.text, id);
+        *collector.entry(term.text.into_owned()).or_insert(0) += 1;
+        length += 1;
+    }
+
+    for (field, text) in extracted.iter().flat_map(|e| &e.fields) {
+        let counts = corpus.fields.entry(field.to_string()).or_default().entry(id).or_default();
+        for term in analyzer.analyze(text) {
+            *counts.entry(term.text.into_owned()).or_insert(0) += 1;
+        }
+    }
+
+    if let Some(info) = corpus.documents.get_mut(id) {
+        if info.title.is_none() {
+            info.title = extracted.and_then(|e| e.title);
+        }
+        info.size += metadata.len();
+        info.modified = info.modified.max(metadata.modified().ok());
+        info.length += length;
+        info.files.push(FileInfo { path: path.to_path_buf(), size: metadata.len(), length });
+    }
+    debug!("{}: {} bytes, {} terms in {:?}", path.display(), metadata.len(), length, started.elapsed());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::indexed;
+
+    fn files(corpus: &Corpus) -> Vec<(DocId, PathBuf, Vec<FileInfo>)> {
+        return corpus.documents.iter()
+            .map(|(id, info)| (id, info.path.clone(), info.files.clone()))
+            .collect();
+    }
+
+    fn postings(corpus: &Corpus) -> Vec<(String, Vec<(DocId, u32)>)> {
+        return corpus.triee.words()
+            .map(|word| {
+                let postings = corpus.triee.postings(&word).unwrap().to_vec();
+                (word, postings)
+            })
+            .collect();
+    }
+
+    #[test]
+    fn save_and_load_keep_ids_files_and_postings() {
+        let mut options = IndexOptions::new(&["json"]);
+        options.granularity = Granularity::Directory;
+        options.ngrams = true;
+        let (tree, mut corpus) = indexed("roundtrip", &[
+            ("a/1.json", br#"{"title": "alpha beta", "n": 3}"#),
+            ("a/2.json", br#"{"title": "beta gamma"}"#),
+            ("b/3.json", br#"{"title": "gamma delta", "when": "2024-01-02"}"#),
+            ("c.json", br#"{"title": "gone"}"#),
+        ], options);
+        // a removed document leaves a gap in the ids
+        let gone = corpus.documents.id_of(&tree.root).unwrap();
+        corpus.remove_document(gone);
+
+        let saved = tree.root.join("index.fji");
+        corpus.save(&saved).unwrap();
+        let loaded = Corpus::load(&saved, IndexOptions::new(&[])).unwrap();
+
+        assert_eq!(files(&loaded), files(&corpus));
+        assert!(loaded.documents.get(gone).is_none());
+        assert_eq!(loaded.index, corpus.index);
+        assert_eq!(loaded.fields, corpus.fields);
+        assert_eq!(postings(&loaded), postings(&corpus));
+        assert_eq!(loaded.options.extensions, corpus.options.extensions);
+        assert_eq!(loaded.options.granularity, Granularity::Directory);
+        assert_eq!(loaded.ngrams.map(|n| n.len()), corpus.ngrams.map(|n| n.len()));
+    }
+
+    #[test]
+    fn failed_update_removes_the_document() {
+        let mut options = IndexOptions::new(&["json"]);
+        options.granularity = Granularity::Directory;
+        let (tree, mut corpus) = indexed("update", &[
+            ("a/1.json", br#"{"title": "alpha"}"#),
+            ("a/2.json", br#"{"title": "beta"}"#),
+            ("b/3.json", br#"{"title": "alpha"}"#),
+        ], options);
+        let id = corpus.documents.id_of(&tree.root.join("a")).unwrap();
+
+        std::fs::remove_file(tree.root.join("a/2.json")).unwrap();
+        assert!(corpus.update_document(id).is_err());
+
+        assert!(corpus.documents.get(id).is_none());
+        assert!(!corpus.index.contains_key(&id));
+        assert_eq!(corpus.triee.postings("alpha").unwrap().len(), 1);
+        assert!(corpus.triee.postings("beta").is_none());
+    }
+
+    #[test]
+    fn binary_files_are_skipped_even_when_failing_fast() {
+        let mut options = IndexOptions::new(&["json"]);
+        options.on_error = ErrorPolicy::FailFast;
+        let (_tree, corpus) = indexed("binary", &[
+            ("a.json", br#"{"title": "alpha"}"#),
+            ("b.json", b"\x00\x01\x02binary"),
+        ], options);
+
+        assert_eq!(corpus.documents.len(), 1);
+        assert!(matches!(&corpus.skipped[..], [Error::Binary { path }] if path.ends_with("b.json")));
+    }
+}
@@ -1,122 +1,516 @@
+use std::collections::VecDeque;
+
+use crate::{escape, segment};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LexerMode {
+    #[default]
+    Plain,
+    /// Identifiers like `parseJsonValue`, `index_single_file` or `max-width`
+    /// are emitted whole, followed by their sub-words.
+    Identifiers,
+    /// UAX #29 word boundaries, with Han and Hiragana runs split into
+    /// overlapping bigrams since those scripts don't use spaces.
+    Unicode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Word,
+    /// Decimal, signed, exponent or `0x` hex number.
+    Number(f64),
+    /// ISO-8601 date or date-time, as days since 1970-01-01 (UTC).
+    Date(f64),
+    Symbol,
+}
+
+impl TokenKind {
+    pub fn of(token: &str) -> Self {
+        let first = match token.chars().next() {
+            Some(first) => first,
+            None => return TokenKind::Symbol,
+        };
+
+        if date_length(token) == Some(token.len()) {
+            if let Some(days) = parse_date(token) {
+                return TokenKind::Date(days);
+            }
+        }
+
+        if number_length(token) == Some(token.len()) {
+            let (sign, digits) = match token.strip_prefix('-') {
+                Some(rest) => (-1.0, rest),
+                None => (1.0, token.strip_prefix('+').unwrap_or(token)),
+            };
+
+            let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+                Some(hex) => i64::from_str_radix(hex, 16).ok().map(|v| v as f64),
+                None => digits.parse::<f64>().ok(),
+            };
+            if let Some(value) = value {
+                return TokenKind::Number(sign * value);
+            }
+        }
+
+        if first.is_alphanumeric() || first == '_' {
+            return TokenKind::Word;
+        }
+        return TokenKind::Symbol;
+    }
+}
+
+/// A token together with its byte offset in the lexed text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    pub start: usize,
+}
+
+impl Token<'_> {
+    pub fn end(&self) -> usize {
+        return self.start + self.text.len();
+    }
+}
+
+/// Splits text into tokens, yielding slices of the input. Sub-words of split
+/// identifiers and CJK bigrams are slices too, so no token is allocated.
+/// Tokens are raw, escapes included; `escape::unescape` gives the decoded text.
 pub struct Lexer<'a> {
-    content: &'a [char],
+    source: &'a str,
+    pos: usize,
+    mode: LexerMode,
+    pending: VecDeque<&'a str>,
 }
 
 impl <'a> Lexer<'a> {
 
-    pub fn new(content: &'a [char]) -> Self {
-        return Self { content };
+    #[allow(dead_code)]
+    pub fn new(content: &'a str) -> Self {
+        return Self::with_mode(content, LexerMode::Plain);
+    }
+
+    pub fn with_mode(content: &'a str, mode: LexerMode) -> Self {
+        return Self { source: content, pos: 0, mode, pending: VecDeque::new() };
+    }
+
+    /// Tokens with their byte offsets.
+    pub fn spanned(self) -> impl Iterator<Item = Token<'a>> {
+        let base = self.source.as_ptr() as usize;
+        return self.map(move |text| Token { text, start: text.as_ptr() as usize - base });
+    }
+
+    fn content(&self) -> &'a str {
+        return &self.source[self.pos..];
+    }
+
+    fn previous_char(&self) -> Option<char> {
+        return self.source[..self.pos].chars().next_back();
+    }
+
+    /// A sign only starts a number where it can't be a binary minus or plus,
+    /// so `5-3` stays three tokens while `[-3` or `x = +3` keep the sign.
+    fn number_ahead(&self) -> Option<usize> {
+        let content = self.content();
+        let signed = content.starts_with(['+', '-']);
+        let sign_allowed = self.previous_char()
+            .map(|c| c.is_whitespace() || "([{,:=".contains(c))
+            .unwrap_or(true);
+        if signed && !sign_allowed {
+            return None;
+        }
+
+        return date_length(content).or_else(|| number_length(content));
     }
 
     fn trim_left(&mut self) {
-        while self.content.len() > 0 && self.content[0].is_ascii_whitespace() {
-            self.content = &self.content[1..]
+        let is_space = if self.mode == LexerMode::Unicode { char::is_whitespace } else { |c: char| c.is_ascii_whitespace() };
+        loop {
+            let content = self.content();
+            self.pos += content.len() - content.trim_start_matches(is_space).len();
+
+            // escaped whitespace in source files (`"one\ntwo"`) separates words too
+            let escaped = match self.mode {
+                LexerMode::Identifiers => escape::escape_at(self.content()).filter(|(c, _)| c.is_whitespace()),
+                _ => None,
+            };
+            match escaped {
+                Some((_, n)) => self.pos += n,
+                None => return,
+            }
         }
     }
 
-    fn chop_while<P>(&mut self, mut predicate: P) -> &'a [char]
+    fn chop_while<P>(&mut self, mut predicate: P) -> &'a str
     where P: FnMut(&char) -> bool {
-        let mut n = 0 as usize;
-        while self.content.len() > 0 && n < self.content.len() && predicate(&self.content[n]) {
-            n += 1;
-        }
+        let content = self.content();
+        let n = content.char_indices()
+            .find(|(_, c)| !predicate(c))
+            .map(|(i, _)| i)
+            .unwrap_or(content.len());
 
         return self.chop(n)
     }
 
+    /// Chops `n` bytes, which must end on a char boundary.
+    fn chop(&mut self, n: usize) -> &'a str {
+        let token = &self.source[self.pos..self.pos + n];
+        self.pos += n;
+        return token;
+    }
+
+    fn chop_char(&mut self) -> &'a str {
+        let n = self.content().chars().next().map(|c| c.len_utf8()).unwrap_or(0);
+        return self.chop(n);
+    }
 
+    /// Source files keep escapes like `caf\u00e9` inside an identifier when
+    /// they decode to identifier chars; the token is still the raw slice.
+    fn chop_identifier(&mut self) -> &'a str {
+        let content = self.content();
+        let mut previous = None;
+        let mut n = 0;
 
-    fn chop(&mut self, n: usize) -> &'a [char] {
-        let mut token = &self.content[0..n];
-        self.content = &self.content[n..];
+        while let Some((c, len)) = escape::decode_char(&content[n..]) {
+            let joins_kebab = c == '-'
+                && previous.map(|p: char| p.is_alphanumeric()).unwrap_or(false)
+                && escape::decode_char(&content[n + len..]).map(|(c, _)| c.is_alphanumeric()).unwrap_or(false);
 
-        let mut resulting = Vec::new();
-        loop {
-            if let Some((c, co)) = Self::sanitize_token(&token[0..]) {
-                resulting.push(c);
-                token = co;
-            } else {
+            if !(c.is_alphanumeric() || c == '_' || joins_kebab) {
                 break;
             }
+            previous = Some(c);
+            n += len;
         }
-        return token;
+
+        return self.chop(n)
     }
 
-    fn sanitize_token(mut content: &[char]) -> Option<(char, &[char])> {
-        if content.is_empty() {
-            return None;
+    /// Splits an identifier on `_`/`-` and on case and digit transitions,
+    /// so `HTTPServer2Config` gives `HTTP`, `Server`, `2`, `Config`.
+    fn split_identifier(ident: &'a str) -> Vec<&'a str> {
+        let mut parts = Vec::new();
+        let mut start = 0;
+        let mut previous: Option<char> = None;
+        let mut i = 0;
+
+        while let Some((c, len)) = escape::decode_char(&ident[i..]) {
+            let at = i;
+            i += len;
+            let prev = previous.replace(c);
+            if c == '_' || c == '-' {
+                if start < at {
+                    parts.push(&ident[start..at]);
+                }
+                start = i;
+                continue;
+            }
+
+            let prev = match prev {
+                Some(prev) if at != start => prev,
+                _ => continue,
+            };
+
+            let next_is_lower = escape::decode_char(&ident[i..]).map(|(n, _)| n.is_lowercase()).unwrap_or(false);
+            let boundary = (prev.is_lowercase() && c.is_uppercase())
+                || (prev.is_uppercase() && c.is_uppercase() && next_is_lower)
+                || (prev.is_alphabetic() && c.is_numeric())
+                || (prev.is_numeric() && c.is_alphabetic());
+
+            if boundary {
+                parts.push(&ident[start..at]);
+                start = at;
+            }
         }
 
-        if content.len() <= 5 || (content[0], content[1]) != ('\\', 'u') {
-            return Some((content[0], &content[1..]));
+        if start < ident.len() {
+            parts.push(&ident[start..]);
         }
+        return parts;
+    }
+
+    fn next_segment(&mut self) -> &'a str {
+        let content = self.content();
+        let run = content.char_indices()
+            .find(|(_, c)| !segment::is_cjk(*c))
+            .map(|(i, _)| i)
+            .unwrap_or(content.len());
 
-        // dbg!(content);
-        let mut bytes = Vec::new();
-        while content.len() > 5 && (content[0], content[1]) == ('\\', 'u') {
-            let byte = content[2..6].iter().collect::<String>();
-            let byte_num = u8::from_str_radix(&byte, 16).ok()?;
-            content = &content[6..];
-            bytes.push(byte_num);
+        if run == 0 {
+            let n = segment::word_length(content);
+            return if n == 0 { self.chop_char() } else { self.chop(n) };
         }
 
-        let resulting_char = std::str::from_utf8(&bytes)
-            .ok()?
-            .chars()
-            .take(1)
-            .next()?;
+        let cjk = self.chop(run);
+        let starts = cjk.char_indices().map(|(i, _)| i).chain([cjk.len()]).collect::<Vec<_>>();
+        if starts.len() <= 3 {
+            return cjk;
+        }
 
-        return Some((resulting_char, content));
+        self.pending.extend(starts.windows(3).skip(1).map(|w| &cjk[w[0]..w[2]]));
+        return &cjk[..starts[2]];
     }
 
-    pub fn next_token(&mut self) -> Option<&'a [char]> {
+    pub fn next_token(&mut self) -> Option<&'a str> {
+        if let Some(part) = self.pending.pop_front() {
+            return Some(part);
+        }
+
         self.trim_left();
 
-        if self.content.len() == 0 {
-            return None;
+        let first = self.content().chars().next()?;
+
+        if let Some(n) = self.number_ahead() {
+            return Some(self.chop(n));
+        }
+
+        if self.mode == LexerMode::Unicode {
+            return Some(self.next_segment());
         }
 
-        if self.content[0].is_alphabetic() {
+        if self.mode == LexerMode::Identifiers {
+            let (decoded, len) = escape::decode_char(self.content())?;
+            if decoded.is_alphabetic() || decoded == '_' {
+                let ident = self.chop_identifier();
+                let parts = Self::split_identifier(ident);
+                if parts.len() > 1 || parts.first().map(|p| p.len() != ident.len()).unwrap_or(false) {
+                    self.pending.extend(parts);
+                }
+                return Some(ident);
+            }
+            if first == '\\' {
+                return Some(self.chop(len));
+            }
+        }
+
+        if first.is_alphabetic() {
             return Some(self.chop_while(|c| c.is_alphanumeric() || *c == '\\'))
         }
 
-        if self.content[0].is_ascii_digit() {
+        if first.is_ascii_digit() {
             return Some(self.chop_while(|c| c.is_numeric()))
         }
 
-        return Some(self.chop(1));
+        return Some(self.chop_char());
+    }
+
+}
+
+fn digits(content: &str, from: usize) -> usize {
+    return content.as_bytes().get(from..).unwrap_or(&[]).iter().take_while(|c| c.is_ascii_digit()).count();
+}
+
+fn ends_token(content: &str, at: usize) -> bool {
+    return content[at..].chars().next().map(|c| !(c.is_alphanumeric() || c == '_')).unwrap_or(true);
+}
+
+/// Length of a number at the start of `content`: optional sign, then either
+/// `0x` and hex digits, or digits with an optional fraction and exponent.
+fn number_length(content: &str) -> Option<usize> {
+    let bytes = content.as_bytes();
+    let mut i = if content.starts_with(['+', '-']) { 1 } else { 0 };
+
+    let hex_prefix = bytes.get(i) == Some(&b'0') && matches!(bytes.get(i + 1), Some(b'x') | Some(b'X'));
+    if hex_prefix && bytes.get(i + 2).map(|c| c.is_ascii_hexdigit()).unwrap_or(false) {
+        i += 2;
+        i += bytes[i..].iter().take_while(|c| c.is_ascii_hexdigit()).count();
+        return if ends_token(content, i) { Some(i) } else { None };
+    }
+
+    let whole = digits(content, i);
+    if whole == 0 {
+        return None;
+    }
+    i += whole;
+
+    if bytes.get(i) == Some(&b'.') && digits(content, i + 1) > 0 {
+        i += 1 + digits(content, i + 1);
+    }
+
+    if matches!(bytes.get(i), Some(b'e') | Some(b'E')) {
+        let sign = if matches!(bytes.get(i + 1), Some(b'+') | Some(b'-')) { 1 } else { 0 };
+        let exponent = digits(content, i + 1 + sign);
+        if exponent > 0 {
+            i += 1 + sign + exponent;
+        }
+    }
+
+    return if ends_token(content, i) { Some(i) } else { None };
+}
+
+fn fixed_number(content: &str, from: usize, len: usize) -> Option<u32> {
+    let part = content.as_bytes().get(from..from + len)?;
+    if part.iter().any(|c| !c.is_ascii_digit()) {
+        return None;
+    }
+    return content[from..from + len].parse().ok();
+}
+
+/// Length of an ISO-8601 date (`2025-01-31`) or date-time
+/// (`2025-01-31T10:00`, seconds, fraction and `Z`/`+01:00` offset optional).
+fn date_length(content: &str) -> Option<usize> {
+    let bytes = content.as_bytes();
+    let month = fixed_number(content, 5, 2)?;
+    let day = fixed_number(content, 8, 2)?;
+    fixed_number(content, 0, 4)?;
+    if bytes[4] != b'-' || bytes[7] != b'-' || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut i = 10;
+    if bytes.get(i) == Some(&b'T') && fixed_number(content, i + 1, 2).is_some()
+        && bytes.get(i + 3) == Some(&b':') && fixed_number(content, i + 4, 2).is_some() {
+        i += 6;
+        if bytes.get(i) == Some(&b':') && fixed_number(content, i + 1, 2).is_some() {
+            i += 3;
+            if bytes.get(i) == Some(&b'.') && digits(content, i + 1) > 0 {
+                i += 1 + digits(content, i + 1);
+            }
+        }
+
+        if bytes.get(i) == Some(&b'Z') {
+            i += 1;
+        } else if matches!(bytes.get(i), Some(b'+') | Some(b'-')) && fixed_number(content, i + 1, 2).is_some() {
+            i += 3;
+            if bytes.get(i) == Some(&b':') && fixed_number(content, i + 1, 2).is_some() {
+                i += 3;
+            } else if fixed_number(content, i, 2).is_some() {
+                i += 2;
+            }
+        }
+    }
+
+    return if ends_token(content, i) { Some(i) } else { None };
+}
+
+fn parse_date(token: &str) -> Option<f64> {
+    let year = fixed_number(token, 0, 4)? as i64;
+    let month = fixed_number(token, 5, 2)? as i64;
+    let day = fixed_number(token, 8, 2)? as i64;
+
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let mut days = (era * 146097 + doe - 719468) as f64;
+
+    if token.len() > 10 {
+        let hours = fixed_number(token, 11, 2)? as f64;
+        let minutes = fixed_number(token, 14, 2)? as f64;
+        let mut rest = &token[16..];
+        let mut seconds = 0.0;
+        if let Some(after) = rest.strip_prefix(':') {
+            let end = after.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(after.len());
+            seconds = after[..end].parse().ok()?;
+            rest = &after[end..];
+        }
+
+        let mut offset = 0.0;
+        if let Some(sign) = rest.chars().next().filter(|c| *c == '+' || *c == '-') {
+            let digits = rest[1..].chars().filter(|c| c.is_ascii_digit()).collect::<String>();
+            let sign = if sign == '-' { -1.0 } else { 1.0 };
+            let (h, m) = digits.split_at(2);
+            offset = sign * (h.parse::<f64>().ok()? * 60.0 + m.parse::<f64>().unwrap_or(0.0));
+        }
+
+        days += (hours * 3600.0 + minutes * 60.0 + seconds - offset * 60.0) / 86400.0;
     }
 
+    return Some(days);
 }
 
 impl <'a> Iterator for Lexer<'a> {
-    type Item = &'a [char];
+    type Item = &'a str;
 
     fn next(&mut self) -> Option<Self::Item> {
         return self.next_token()
     }
 }
 
-// fn unescape(s: &[u8]) -> Result<&[char], Box<dyn Error>> {
-//     let mut output = Vec::new();
-//     let mut i = 0;
-
-//     while i < s.len() {
-//         match s[i] {
-//             b'\\' => {
-//                 i += 1;
-//                 match s[i] {
-//                     b'u' => {
-//                         let num = u8::from_str_radix(std::str::from_utf8(&s[i+1..][..4])?, 16)?;
-//                         output.push(num);
-//                         i += 4;
-//                     }
-//                     byte => output.push(byte),
-//                 }
-//             },
-//             byte => output.push(byte),
-//         }
-//         i += 1;
-//     }
-//     Ok(&String::from_utf8(output)?.chars().collect::<Vec<_>>())
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identifiers(text: &str) -> Vec<&str> {
+        return Lexer::with_mode(text, LexerMode::Identifiers).collect();
+    }
+
+    #[test]
+    fn identifiers_split_on_case_and_separators() {
+        assert_eq!(identifiers("parseJsonValue"), ["parseJsonValue", "parse", "Json", "Value"]);
+        assert_eq!(identifiers("index_single_file"), ["index_single_file", "index", "single", "file"]);
+        assert_eq!(identifiers("max-width: 3"), ["max-width", "max", "width", ":", "3"]);
+        assert_eq!(identifiers("__init__"), ["__init__", "init"]);
+        assert_eq!(identifiers("plain words"), ["plain", "words"]);
+    }
+
+    #[test]
+    fn acronyms_and_digits_split_off() {
+        assert_eq!(identifiers("HTTPServer2Config"), ["HTTPServer2Config", "HTTP", "Server", "2", "Config"]);
+        assert_eq!(identifiers("parseHTTP"), ["parseHTTP", "parse", "HTTP"]);
+        assert_eq!(identifiers("IOError"), ["IOError", "IO", "Error"]);
+        assert_eq!(identifiers("utf8Decode"), ["utf8Decode", "utf", "8", "Decode"]);
+    }
+
+    #[test]
+    fn kebab_needs_letters_on_both_sides() {
+        assert_eq!(identifiers("a - b"), ["a", "-", "b"]);
+        assert_eq!(identifiers("x-"), ["x", "-"]);
+        assert_eq!(identifiers("a-b-"), ["a-b", "a", "b", "-"]);
+    }
+
+    #[test]
+    fn escapes_stay_inside_identifiers() {
+        assert_eq!(identifiers(r"caf\u00e9Noir"), [r"caf\u00e9Noir", r"caf\u00e9", "Noir"]);
+        assert_eq!(identifiers(r"one\ntwo"), ["one", "two"]);
+    }
+
+    fn plain(text: &str) -> Vec<&str> {
+        return Lexer::new(text).collect();
+    }
+
+    #[test]
+    fn numbers_lex_with_sign_fraction_and_exponent() {
+        assert_eq!(plain("x = -3.5e2, +7 and 0x1F"), ["x", "=", "-3.5e2", ",", "+7", "and", "0x1F"]);
+        assert_eq!(plain("[-1,-2]"), ["[", "-1", ",", "-2", "]"]);
+        // a sign after a value is an operator
+        assert_eq!(plain("5-3 a+1"), ["5", "-", "3", "a", "+", "1"]);
+        // no digits after the dot or exponent, so they aren't part of it
+        assert_eq!(plain("3. 2e"), ["3", ".", "2", "e"]);
+        assert_eq!(plain("1.5.2"), ["1.5", ".", "2"]);
+    }
+
+    #[test]
+    fn number_values() {
+        assert_eq!(TokenKind::of("-3.5e2"), TokenKind::Number(-350.0));
+        assert_eq!(TokenKind::of("+7"), TokenKind::Number(7.0));
+        assert_eq!(TokenKind::of("1E-3"), TokenKind::Number(0.001));
+        assert_eq!(TokenKind::of("0x1F"), TokenKind::Number(31.0));
+        assert_eq!(TokenKind::of("-0X10"), TokenKind::Number(-16.0));
+        assert_eq!(TokenKind::of("12abc"), TokenKind::Word);
+        assert_eq!(TokenKind::of("0xZZ"), TokenKind::Word);
+        assert_eq!(TokenKind::of("-"), TokenKind::Symbol);
+    }
+
+    #[test]
+    fn dates_lex_as_one_token() {
+        assert_eq!(plain("on 2025-01-31 at"), ["on", "2025-01-31", "at"]);
+        assert_eq!(plain("2025-01-31T10:00:59.5+01:00 x"), ["2025-01-31T10:00:59.5+01:00", "x"]);
+        assert_eq!(plain("2025-01-31T10:00Z"), ["2025-01-31T10:00Z"]);
+        // out of range months and days aren't dates
+        assert_eq!(plain("2025-13-01"), ["2025", "-", "13", "-", "01"]);
+        assert_eq!(plain("2025-01-32"), ["2025", "-", "01", "-", "32"]);
+        assert_eq!(plain("2025-01-31x"), ["2025", "-", "01", "-", "31", "x"]);
+    }
+
+    #[test]
+    fn date_values_are_days_since_the_epoch() {
+        assert_eq!(TokenKind::of("1970-01-01"), TokenKind::Date(0.0));
+        assert_eq!(TokenKind::of("2000-03-01"), TokenKind::Date(11017.0));
+        assert_eq!(TokenKind::of("1969-12-31"), TokenKind::Date(-1.0));
+        assert_eq!(TokenKind::of("1970-01-02T12:00Z"), TokenKind::Date(1.5));
+        assert_eq!(TokenKind::of("1970-01-02T12:00:00+01:00"), TokenKind::Date(1.0 + 11.0 / 24.0));
+        assert_eq!(TokenKind::of("1970-01-01T00:00:00-0600"), TokenKind::Date(0.25));
+    }
+}
@@ -0,0 +1,115 @@
+//! Leveled messages on stderr, and a status line (like indexing progress)
+//! that messages are printed above.
+
+use std::{
+    fmt,
+    io::{IsTerminal, Write},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex,
+    },
+};
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+static STATUS: Mutex<Option<String>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Level {
+    /// Errors only.
+    Quiet,
+    /// Warnings, progress and summaries.
+    #[default]
+    Normal,
+    /// Every file indexed too.
+    Verbose,
+    /// And what came of each, with timings.
+    Debug,
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Self> {
+        return match name {
+            "quiet" => Some(Level::Quiet),
+            "normal" => Some(Level::Normal),
+            "verbose" => Some(Level::Verbose),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        };
+    }
+
+    /// One step more verbose, staying at `Debug`.
+    pub fn louder(self) -> Self {
+        return match self {
+            Level::Quiet => Level::Normal,
+            Level::Normal => Level::Verbose,
+            _ => Level::Debug,
+        };
+    }
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Level {
+    return match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Quiet,
+        1 => Level::Normal,
+        2 => Level::Verbose,
+        _ => Level::Debug,
+    };
+}
+
+pub fn enabled(level: Level) -> bool {
+    return level <= self::level();
+}
+
+/// Prints a line to stderr, above the status line if one is shown.
+pub fn write(args: fmt::Arguments) {
+    let status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut stderr = std::io::stderr().lock();
+    let _ = match status.as_deref() {
+        Some(line) => write!(stderr, "\r\x1b[K{}\n{}", args, line),
+        None => writeln!(stderr, "{}", args),
+    };
+}
+
+/// Replaces the status line, or clears it with `None`. Only shown when
+/// stderr is a terminal.
+pub fn status(line: Option<String>) {
+    let stderr = std::io::stderr();
+    if !stderr.is_terminal() {
+        return;
+    }
+    let mut status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut stderr = stderr.lock();
+    let _ = write!(stderr, "\r\x1b[K{}", line.as_deref().unwrap_or(""));
+    let _ = stderr.flush();
+    *status = line;
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Normal) {
+            $crate::log::write(format_args!($($arg)*));
+        }
+    };
+}
+
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Verbose) {
+            $crate::log::write(format_args!($($arg)*));
+        }
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Debug) {
+            $crate::log::write(format_args!($($arg)*));
+        }
+    };
+}
+
+pub(crate) use {debug, info, verbose};
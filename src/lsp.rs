@@ -0,0 +1,406 @@
+//! A language server over stdio: `workspace/symbol` finds indexed terms and
+//! where they occur, `fji/search` runs a ranked full-text search. Messages
+//! are JSON-RPC with `Content-Length` framing.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufRead, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    documents::DocId,
+    error::{Error, Result},
+    index_files::{index, Corpus, IndexOptions},
+    lexer::TokenKind,
+    parse::{parse_json, JsonValue},
+    report,
+    search::search_term,
+    server::percent_decode,
+    trie::Ranking,
+};
+
+const SYMBOL_LIMIT: usize = 100;
+const SEARCH_LIMIT: usize = 15;
+/// Longest header line and largest message body accepted.
+const MAX_HEADER: usize = 8192;
+const MAX_BODY: usize = 16 << 20;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INTERNAL_ERROR: i64 = -32603;
+const SERVER_NOT_INITIALIZED: i64 = -32002;
+
+// LSP `SymbolKind`s
+const KIND_CONSTANT: i64 = 14;
+const KIND_NUMBER: i64 = 16;
+const KIND_VARIABLE: i64 = 13;
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: &str) -> Self {
+        return Self { code, message: message.to_owned() };
+    }
+}
+
+pub struct LanguageServer {
+    /// Given up front, or indexed from the workspace root on `initialize`.
+    corpus: Option<Corpus>,
+    options: IndexOptions,
+}
+
+impl LanguageServer {
+
+    pub fn new(corpus: Option<Corpus>, options: IndexOptions) -> Self {
+        return Self { corpus, options };
+    }
+
+    /// Serves stdin and stdout until `exit` or the end of input.
+    pub fn run(&mut self) -> Result<()> {
+        let stdin = std::io::stdin();
+        let mut input = stdin.lock();
+        let stdin_error = |e| Error::io(Path::new("<stdin>"), e);
+
+        while let Some(body) = read_message(&mut input).map_err(stdin_error)? {
+            let message = match parse_json(body) {
+                Ok(message) => message,
+                Err(e) => {
+                    send(&error_response(JsonValue::Null, RpcError::new(PARSE_ERROR, &e.to_string())))?;
+                    continue;
+                }
+            };
+
+            let method = message.get("method").and_then(JsonValue::as_str).unwrap_or("");
+            if method == "exit" {
+                return Ok(());
+            }
+            // notifications have no id and get no answer
+            let id = match message.get("id") {
+                Some(id) => id.clone(),
+                None => continue,
+            };
+
+            let params = message.get("params").cloned().unwrap_or(JsonValue::Null);
+            let response = match self.handle(method, &params) {
+                Ok(result) => JsonValue::object(vec![
+                    ("jsonrpc", JsonValue::string("2.0")),
+                    ("id", id),
+                    ("result", result),
+                ]),
+                Err(e) => error_response(id, e),
+            };
+            send(&response)?;
+        }
+        return Ok(());
+    }
+
+    fn handle(&mut self, method: &str, params: &JsonValue) -> std::result::Result<JsonValue, RpcError> {
+        if method == "initialize" {
+            return self.initialize(params);
+        }
+        if method == "shutdown" {
+            return Ok(JsonValue::Null);
+        }
+
+        let corpus = match (&self.corpus, method) {
+            (Some(corpus), _) => corpus,
+            (None, "workspace/symbol" | "fji/search") => {
+                return Err(RpcError::new(SERVER_NOT_INITIALIZED, "Nothing is indexed yet"));
+            }
+            (None, _) => return Err(RpcError::new(METHOD_NOT_FOUND, method)),
+        };
+        let query = params.get("query").and_then(JsonValue::as_str);
+
+        return match method {
+            "workspace/symbol" => Ok(workspace_symbols(corpus, query.unwrap_or(""))),
+            "fji/search" => {
+                let query = query.ok_or_else(|| RpcError::new(INVALID_PARAMS, "Missing query"))?;
+                let limit = params.get("limit").and_then(JsonValue::as_i64)
+                    .map(|l| l.max(0) as usize)
+                    .unwrap_or(SEARCH_LIMIT);
+                let explain = params.get("explain") == Some(&JsonValue::BooleanValue(true));
+                search(corpus, query, limit, explain)
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, method)),
+        };
+    }
+
+    fn initialize(&mut self, params: &JsonValue) -> std::result::Result<JsonValue, RpcError> {
+        if self.corpus.is_none() {
+            let root = params.get("rootUri").and_then(JsonValue::as_str).and_then(uri_to_path)
+                .or_else(|| params.get("rootPath").and_then(JsonValue::as_str).map(PathBuf::from))
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "No rootUri to index"))?;
+            let corpus = index(&root, self.options.clone())
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, &e.to_string()))?;
+            self.corpus = Some(corpus);
+        }
+
+        return Ok(JsonValue::object(vec![
+            ("capabilities", JsonValue::object(vec![
+                ("workspaceSymbolProvider", JsonValue::BooleanValue(true)),
+            ])),
+            ("serverInfo", JsonValue::object(vec![
+                ("name", JsonValue::string(env!("CARGO_PKG_NAME"))),
+                ("version", JsonValue::string(env!("CARGO_PKG_VERSION"))),
+            ])),
+        ]));
+    }
+}
+
+/// The most widespread terms starting with `query`, at their first
+/// occurrence in each document that has them.
+fn workspace_symbols(corpus: &Corpus, query: &str) -> JsonValue {
+    let mut wanted: BTreeMap<DocId, Vec<String>> = BTreeMap::new();
+    let mut count = 0;
+    'words: for (word, _) in corpus.triee.complete(&query.to_lowercase(), SYMBOL_LIMIT, Ranking::DocumentFrequency) {
+        for (doc, _) in corpus.triee.postings(&word).unwrap_or(&[]) {
+            if count == SYMBOL_LIMIT {
+                break 'words;
+            }
+            wanted.entry(*doc).or_default().push(word.clone());
+            count += 1;
+        }
+    }
+
+    let mut symbols = Vec::new();
+    for (id, words) in wanted {
+        let mut found: HashMap<&str, JsonValue> = HashMap::new();
+        for (path, text) in corpus.read_document(id).unwrap_or_default() {
+            let text = Corpus::visible_text(&path, text);
+            for term in corpus.options.analyzers.for_path(&path).analyze(&text) {
+                let word = match words.iter().find(|w| **w == term.text) {
+                    Some(word) if !found.contains_key(word.as_str()) => word,
+                    _ => continue,
+                };
+                let kind = match term.kind {
+                    TokenKind::Number(_) => KIND_NUMBER,
+                    TokenKind::Date(_) => KIND_CONSTANT,
+                    _ => KIND_VARIABLE,
+                };
+                found.insert(word, JsonValue::object(vec![
+                    ("name", JsonValue::string(&text[term.start..term.end])),
+                    ("kind", JsonValue::IntegerValue(kind)),
+                    ("location", location(&path, &text, term.start, term.end)),
+                    ("containerName", JsonValue::string(&corpus.documents.path(id).display().to_string())),
+                ]));
+            }
+        }
+        // keep the ranking order of the words
+        symbols.extend(words.iter().filter_map(|word| found.remove(word.as_str())));
+    }
+    return JsonValue::ArrayValue(symbols);
+}
+
+fn search(corpus: &Corpus, query: &str, limit: usize, explain: bool) -> std::result::Result<JsonValue, RpcError> {
+    let results = search_term(query.to_owned(), corpus)
+        .map_err(|e| RpcError::new(INVALID_PARAMS, &e.to_string()))?;
+    let results = results.iter().rev().take(limit)
+        .map(|(id, score)| {
+            let mut result = report::result(query, corpus, *id, *score, explain);
+            if let JsonValue::ObjectValue(pairs) = &mut result {
+                pairs.insert("uri".to_owned(), JsonValue::string(&path_to_uri(corpus.documents.path(*id))));
+            }
+            result
+        })
+        .collect();
+    return Ok(JsonValue::ArrayValue(results));
+}
+
+fn location(path: &Path, text: &str, start: usize, end: usize) -> JsonValue {
+    return JsonValue::object(vec![
+        ("uri", JsonValue::string(&path_to_uri(path))),
+        ("range", JsonValue::object(vec![
+            ("start", position(text, start)),
+            ("end", position(text, end)),
+        ])),
+    ]);
+}
+
+/// LSP positions count lines and UTF-16 code units.
+fn position(text: &str, offset: usize) -> JsonValue {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    return JsonValue::object(vec![
+        ("line", JsonValue::IntegerValue(before.matches('\n').count() as i64)),
+        ("character", JsonValue::IntegerValue(before[line_start..].encode_utf16().count() as i64)),
+    ]);
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    return Some(PathBuf::from(percent_decode(path)));
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = "file://".to_owned();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => uri.push(byte as char),
+            byte => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    return uri;
+}
+
+/// Body of the next message, `None` at the end of input.
+fn read_message(input: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        let read = input.by_ref().take(MAX_HEADER as u64).read_line(&mut line)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if !line.ends_with('\n') && read == MAX_HEADER {
+            return Err(invalid(format!("Header line over {} bytes", MAX_HEADER)));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(invalid(format!("Content-Length {} is over the limit of {} bytes", length, MAX_BODY)));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    return Ok(Some(String::from_utf8_lossy(&body).into_owned()));
+}
+
+fn send(message: &JsonValue) -> Result<()> {
+    let body = message.to_string();
+    let mut stdout = std::io::stdout().lock();
+    return write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| stdout.flush())
+        .map_err(|e| Error::io(Path::new("<stdout>"), e));
+}
+
+fn error_response(id: JsonValue, error: RpcError) -> JsonValue {
+    return JsonValue::object(vec![
+        ("jsonrpc", JsonValue::string("2.0")),
+        ("id", id),
+        ("error", JsonValue::object(vec![
+            ("code", JsonValue::IntegerValue(error.code)),
+            ("message", JsonValue::string(&error.message)),
+        ])),
+    ]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempTree;
+
+    fn read_all(input: &str) -> std::io::Result<Vec<String>> {
+        let mut input = input.as_bytes();
+        let mut bodies = Vec::new();
+        while let Some(body) = read_message(&mut input)? {
+            bodies.push(body);
+        }
+        return Ok(bodies);
+    }
+
+    fn params(json: &str) -> JsonValue {
+        return parse_json(json.to_owned()).unwrap();
+    }
+
+    fn error_code(result: std::result::Result<JsonValue, RpcError>) -> Option<i64> {
+        return result.err().map(|e| e.code);
+    }
+
+    #[test]
+    fn messages_are_framed_by_content_length() {
+        let input = "Content-Length: 2\r\n\r\n{}\
+            content-length: 8\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n[1,\"é\"]\
+            \r\nContent-Length: 4\r\n\r\nnull";
+        assert_eq!(read_all(input).unwrap(), ["{}", "[1,\"é\"]", "null"]);
+        assert!(read_all("").unwrap().is_empty());
+        // the body is shorter than announced
+        assert!(read_all("Content-Length: 10\r\n\r\n{}").is_err());
+    }
+
+    #[test]
+    fn oversized_messages_are_refused() {
+        let huge = format!("Content-Length: {}\r\n\r\n", MAX_BODY + 1);
+        assert_eq!(read_all(&huge).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        let long_header = format!("X-Padding: {}\r\nContent-Length: 2\r\n\r\n{{}}", "x".repeat(MAX_HEADER));
+        assert_eq!(read_all(&long_header).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn initialize_indexes_the_workspace_root() {
+        let tree = TempTree::new("lsp-init", &[
+            ("a.json", br#"{"name": "alpha"}"#),
+            ("b.json", "{\n  \"note\": \"é alphabet\",\n  \"n\": 12\n}".as_bytes()),
+        ]);
+        let mut server = LanguageServer::new(None, IndexOptions::new(&["json"]));
+        let query = params(r#"{"query": "alp"}"#);
+
+        assert_eq!(error_code(server.handle("workspace/symbol", &query)), Some(SERVER_NOT_INITIALIZED));
+        assert_eq!(error_code(server.handle("initialize", &params("{}"))), Some(INVALID_PARAMS));
+
+        let init = format!(r#"{{"rootUri": "{}"}}"#, path_to_uri(&tree.root));
+        let result = server.handle("initialize", &params(&init)).ok().unwrap();
+        let capabilities = result.get("capabilities").unwrap();
+        assert_eq!(capabilities.get("workspaceSymbolProvider"), Some(&JsonValue::BooleanValue(true)));
+        assert_eq!(server.corpus.as_ref().map(|c| c.documents.len()), Some(2));
+
+        assert_eq!(server.handle("shutdown", &JsonValue::Null).ok(), Some(JsonValue::Null));
+        assert_eq!(error_code(server.handle("textDocument/hover", &JsonValue::Null)), Some(METHOD_NOT_FOUND));
+        assert_eq!(error_code(server.handle("fji/search", &params("{}"))), Some(INVALID_PARAMS));
+    }
+
+    #[test]
+    fn workspace_symbols_point_at_first_occurrences() {
+        let tree = TempTree::new("lsp-symbols", &[
+            ("a.json", br#"{"name": "alpha", "other": "alpha"}"#),
+            ("b.json", "{\n  \"note\": \"é Alphabet\",\n  \"n\": 12\n}".as_bytes()),
+        ]);
+        let corpus = index(&tree.root, IndexOptions::new(&["json"])).unwrap();
+        let mut server = LanguageServer::new(Some(corpus), IndexOptions::new(&["json"]));
+
+        let symbols = match server.handle("workspace/symbol", &params(r#"{"query": "ALP"}"#)).ok() {
+            Some(JsonValue::ArrayValue(symbols)) => symbols,
+            other => panic!("not symbols: {:?}", other.map(|s| s.to_string())),
+        };
+        let mut found = symbols.iter()
+            .map(|symbol| {
+                let location = symbol.get("location").unwrap();
+                let start = location.get("range").and_then(|r| r.get("start")).unwrap();
+                (
+                    symbol.get("name").and_then(JsonValue::as_str).unwrap().to_owned(),
+                    location.get("uri").and_then(JsonValue::as_str).unwrap().to_owned(),
+                    start.get("line").and_then(JsonValue::as_i64).unwrap(),
+                    start.get("character").and_then(JsonValue::as_i64).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        found.sort();
+
+        assert_eq!(found, [
+            ("Alphabet".to_owned(), path_to_uri(&tree.root.join("b.json")), 1, 13),
+            ("alpha".to_owned(), path_to_uri(&tree.root.join("a.json")), 0, 10),
+        ]);
+        match server.handle("fji/search", &params(r#"{"query": "alpha", "limit": 1}"#)).ok() {
+            Some(JsonValue::ArrayValue(results)) => assert_eq!(results.len(), 1),
+            other => panic!("not results: {:?}", other.map(|s| s.to_string())),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{binary::{self, Reader}, documents::DocId, error::{Error, Result}};
+
+pub type Trigram = [char; 3];
+
+/// Character trigram index over document contents. Text is lowercased before
+/// it is split, so lookups are case-insensitive. It only narrows down
+/// candidates: callers still have to verify a document really matches.
+#[derive(Default)]
+pub struct NgramIndex {
+    grams: HashMap<Trigram, BTreeSet<DocId>>,
+}
+
+#[allow(dead_code)]
+impl NgramIndex {
+
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn insert_text(&mut self, text: &str, doc: DocId) {
+        for gram in trigrams(text) {
+            self.grams.entry(gram).or_default().insert(doc);
+        }
+    }
+
+    pub fn remove_doc(&mut self, doc: DocId) {
+        self.grams.retain(|_, docs| {
+            docs.remove(&doc);
+            !docs.is_empty()
+        });
+    }
+
+    /// Documents containing every trigram of `fragment`, or `None` when the
+    /// fragment is too short to have any and so can't narrow the search.
+    pub fn candidates(&self, fragment: &str) -> Option<BTreeSet<DocId>> {
+        let grams = trigrams(fragment).collect::<BTreeSet<_>>();
+        if grams.is_empty() {
+            return None;
+        }
+
+        let mut result: Option<BTreeSet<DocId>> = None;
+        for gram in grams {
+            let docs = match self.grams.get(&gram) {
+                Some(docs) => docs,
+                None => return Some(BTreeSet::new()),
+            };
+            result = Some(match result {
+                None => docs.clone(),
+                Some(r) => r.intersection(docs).copied().collect(),
+            });
+        }
+        return result;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.grams.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.grams.is_empty();
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        binary::write_varint(out, self.grams.len() as u64);
+        for (gram, docs) in self.grams.iter() {
+            for c in gram {
+                binary::write_varint(out, *c as u64);
+            }
+            binary::write_doc_ids(out, docs.iter());
+        }
+    }
+
+    pub fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let mut index = Self::new();
+        for _ in 0..reader.varint()? {
+            let mut gram = ['\0'; 3];
+            for c in gram.iter_mut() {
+                *c = char::from_u32(reader.varint()? as u32)
+                    .ok_or_else(|| Error::IndexFormat("Invalid char in trigram".to_owned()))?;
+            }
+            index.grams.insert(gram, reader.doc_ids()?);
+        }
+        return Ok(index);
+    }
+}
+
+pub fn trigrams(text: &str) -> impl Iterator<Item = Trigram> {
+    let chars = text.to_lowercase().chars().collect::<Vec<_>>();
+    return (0..chars.len().saturating_sub(2))
+        .map(move |i| [chars[i], chars[i + 1], chars[i + 2]]);
+}
@@ -0,0 +1,251 @@
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, ops::Bound};
+
+use crate::{binary::{self, Reader}, documents::DocId, error::Result, lexer::TokenKind};
+
+/// Field every number is filed under, besides the key it was found after.
+pub const NUMBER_FIELD: &str = "number";
+/// Field every date is filed under, besides the key it was found after.
+pub const DATE_FIELD: &str = "date";
+
+/// Side index of typed tokens for range queries, per field. A field is the
+/// lowercased word a value directly follows (`"price": 12` files `12` under
+/// `price`), and every value is also filed under `number` or `date`.
+#[derive(Default)]
+pub struct NumericIndex {
+    fields: HashMap<String, BTreeMap<u64, BTreeSet<DocId>>>,
+}
+
+/// Maps an `f64` to a `u64` with the same ordering, so values can be keys.
+fn ordered_key(value: f64) -> u64 {
+    let bits = value.to_bits();
+    return if bits >> 63 == 1 { !bits } else { bits | (1 << 63) };
+}
+
+#[allow(dead_code)]
+impl NumericIndex {
+
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn insert(&mut self, field: Option<&str>, kind: TokenKind, doc: DocId) {
+        let (value, general) = match kind {
+            TokenKind::Number(v) => (v, NUMBER_FIELD),
+            TokenKind::Date(v) => (v, DATE_FIELD),
+            _ => return,
+        };
+        if value.is_nan() {
+            return;
+        }
+
+        for name in std::iter::once(general).chain(field) {
+            self.fields.entry(name.to_lowercase())
+                .or_default()
+                .entry(ordered_key(value))
+                .or_default()
+                .insert(doc);
+        }
+    }
+
+    pub fn remove_doc(&mut self, doc: DocId) {
+        for values in self.fields.values_mut() {
+            values.retain(|_, docs| {
+                docs.remove(&doc);
+                !docs.is_empty()
+            });
+        }
+        self.fields.retain(|_, values| !values.is_empty());
+    }
+
+    pub fn range(&self, query: &RangeQuery) -> BTreeSet<DocId> {
+        let values = match self.fields.get(&query.field) {
+            Some(values) => values,
+            None => return BTreeSet::new(),
+        };
+
+        let bound = |b: &Bound<f64>| match b {
+            Bound::Included(v) => Bound::Included(ordered_key(*v)),
+            Bound::Excluded(v) => Bound::Excluded(ordered_key(*v)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        let (lower, upper) = (bound(&query.lower), bound(&query.upper));
+        // BTreeMap::range panics on inverted or empty exclusive bounds
+        if let (Bound::Included(l) | Bound::Excluded(l), Bound::Included(u) | Bound::Excluded(u)) = (lower, upper) {
+            let both_inclusive = matches!((lower, upper), (Bound::Included(_), Bound::Included(_)));
+            if l > u || (l == u && !both_inclusive) {
+                return BTreeSet::new();
+            }
+        }
+
+        return values.range((lower, upper))
+            .flat_map(|(_, docs)| docs.iter().copied())
+            .collect();
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = &String> {
+        return self.fields.keys();
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        binary::write_varint(out, self.fields.len() as u64);
+        for (field, values) in self.fields.iter() {
+            binary::write_str(out, field);
+            binary::write_varint(out, values.len() as u64);
+            for (key, docs) in values.iter() {
+                binary::write_varint(out, *key);
+                binary::write_doc_ids(out, docs.iter());
+            }
+        }
+    }
+
+    pub fn decode_from(reader: &mut Reader) -> Result<Self> {
+        let mut index = Self::new();
+        for _ in 0..reader.varint()? {
+            let field = reader.str()?.to_owned();
+            let mut values = BTreeMap::new();
+            for _ in 0..reader.varint()? {
+                let key = reader.varint()?;
+                values.insert(key, reader.doc_ids()?);
+            }
+            index.fields.insert(field, values);
+        }
+        return Ok(index);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeQuery {
+    pub field: String,
+    pub lower: Bound<f64>,
+    pub upper: Bound<f64>,
+}
+
+impl RangeQuery {
+    /// Parses `field:[a TO b]` (inclusive), `field:{a TO b}` (exclusive),
+    /// `*` for an open end, and `field:>v`, `>=`, `<`, `<=` or `field:v` for an exact value.
+    /// Values are numbers or ISO-8601 dates, typed the way the `Lexer` does.
+    pub fn parse(query: &str) -> Option<Self> {
+        let (field, spec) = query.split_once(':')?;
+        if field.is_empty() || !field.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+            return None;
+        }
+        let field = field.to_lowercase();
+
+        let (lower, upper) = if let Some(body) = spec.strip_prefix('[').and_then(|b| b.strip_suffix(']')) {
+            Self::parse_between(body, true)?
+        } else if let Some(body) = spec.strip_prefix('{').and_then(|b| b.strip_suffix('}')) {
+            Self::parse_between(body, false)?
+        } else if let Some(v) = spec.strip_prefix(">=") {
+            (Bound::Included(parse_value(v)?), Bound::Unbounded)
+        } else if let Some(v) = spec.strip_prefix("<=") {
+            (Bound::Unbounded, Bound::Included(parse_value(v)?))
+        } else if let Some(v) = spec.strip_prefix('>') {
+            (Bound::Excluded(parse_value(v)?), Bound::Unbounded)
+        } else if let Some(v) = spec.strip_prefix('<') {
+            (Bound::Unbounded, Bound::Excluded(parse_value(v)?))
+        } else {
+            let v = parse_value(spec.strip_prefix('=').unwrap_or(spec))?;
+            (Bound::Included(v), Bound::Included(v))
+        };
+
+        return Some(Self { field, lower, upper });
+    }
+
+    pub fn contains(&self, value: f64) -> bool {
+        return std::ops::RangeBounds::contains(&(self.lower, self.upper), &value);
+    }
+
+    fn parse_between(body: &str, inclusive: bool) -> Option<(Bound<f64>, Bound<f64>)> {
+        let (from, to) = body.split_once(" TO ")?;
+        let bound = |v: &str| -> Option<Bound<f64>> {
+            let v = v.trim();
+            if v == "*" {
+                return Some(Bound::Unbounded);
+            }
+            let value = parse_value(v)?;
+            return Some(if inclusive { Bound::Included(value) } else { Bound::Excluded(value) });
+        };
+        return Some((bound(from)?, bound(to)?));
+    }
+}
+
+fn parse_value(value: &str) -> Option<f64> {
+    return match TokenKind::of(value.trim()) {
+        TokenKind::Number(v) | TokenKind::Date(v) => Some(v),
+        _ => None,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Bound::{Excluded, Included, Unbounded};
+
+    fn range(field: &str, lower: Bound<f64>, upper: Bound<f64>) -> Option<RangeQuery> {
+        return Some(RangeQuery { field: field.to_owned(), lower, upper });
+    }
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(RangeQuery::parse("price:[10 TO 20]"), range("price", Included(10.0), Included(20.0)));
+        assert_eq!(RangeQuery::parse("price:{10 TO 20}"), range("price", Excluded(10.0), Excluded(20.0)));
+        assert_eq!(RangeQuery::parse("Price:[* TO -1.5e1]"), range("price", Unbounded, Included(-15.0)));
+        assert_eq!(RangeQuery::parse("n:[0x10 TO *]"), range("n", Included(16.0), Unbounded));
+        assert_eq!(RangeQuery::parse("date:[1970-01-02 TO 1970-01-03T12:00Z]"), range("date", Included(1.0), Included(2.5)));
+    }
+
+    #[test]
+    fn parse_comparisons() {
+        assert_eq!(RangeQuery::parse("n:>5"), range("n", Excluded(5.0), Unbounded));
+        assert_eq!(RangeQuery::parse("n:>=5"), range("n", Included(5.0), Unbounded));
+        assert_eq!(RangeQuery::parse("n:<5"), range("n", Unbounded, Excluded(5.0)));
+        assert_eq!(RangeQuery::parse("n:<=-5"), range("n", Unbounded, Included(-5.0)));
+        assert_eq!(RangeQuery::parse("n:5"), range("n", Included(5.0), Included(5.0)));
+        assert_eq!(RangeQuery::parse("n:=5"), range("n", Included(5.0), Included(5.0)));
+    }
+
+    #[test]
+    fn parse_rejects_what_isnt_a_range() {
+        assert_eq!(RangeQuery::parse("title:rust"), None);
+        assert_eq!(RangeQuery::parse(":5"), None);
+        assert_eq!(RangeQuery::parse("a.b:5"), None);
+        assert_eq!(RangeQuery::parse("n:[1 20]"), None);
+        assert_eq!(RangeQuery::parse("n:[1 TO 20"), None);
+        assert_eq!(RangeQuery::parse("n:>x"), None);
+        assert_eq!(RangeQuery::parse("plain"), None);
+    }
+
+    #[test]
+    fn range_finds_documents_between_bounds() {
+        let mut index = NumericIndex::new();
+        for (doc, value) in [(1, -2.0), (2, 0.0), (3, 5.0), (4, 5.0), (5, 12.5)] {
+            index.insert(Some("Price"), TokenKind::Number(value), doc);
+        }
+        index.insert(None, TokenKind::Date(3.0), 6);
+        let docs = |query: &str| index.range(&RangeQuery::parse(query).unwrap()).into_iter().collect::<Vec<_>>();
+
+        assert_eq!(docs("price:[0 TO 5]"), [2, 3, 4]);
+        assert_eq!(docs("price:{0 TO 5}"), Vec::<DocId>::new());
+        assert_eq!(docs("price:{-2 TO 12.5}"), [2, 3, 4]);
+        assert_eq!(docs("price:<0"), [1]);
+        assert_eq!(docs("price:>=5"), [3, 4, 5]);
+        assert_eq!(docs("price:5"), [3, 4]);
+        assert_eq!(docs("number:[* TO *]"), [1, 2, 3, 4, 5]);
+        assert_eq!(docs("date:>1970-01-01"), [6]);
+        assert_eq!(docs("weight:[0 TO 5]"), Vec::<DocId>::new());
+    }
+
+    #[test]
+    fn inverted_and_empty_bounds_match_nothing() {
+        let mut index = NumericIndex::new();
+        index.insert(None, TokenKind::Number(5.0), 1);
+        let docs = |query: &str| index.range(&RangeQuery::parse(query).unwrap());
+
+        assert!(docs("number:[9 TO 1]").is_empty());
+        assert!(docs("number:{5 TO 5}").is_empty());
+        assert!(docs("number:>5").is_empty());
+        assert_eq!(docs("number:[5 TO 5]").len(), 1);
+        assert!(!RangeQuery::parse("number:[9 TO 1]").unwrap().contains(5.0));
+    }
+}
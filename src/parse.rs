@@ -1,6 +1,8 @@
 #![allow(dead_code)]
 
-use std::{iter::Peekable, str::Chars, collections::HashMap, error::Error};
+use std::{iter::Peekable, str::Chars, collections::HashMap, fmt};
+
+use crate::{error::Error, escape};
 
 #[derive(Debug, PartialEq, Clone)]
 enum JsonTokens {
@@ -17,7 +19,7 @@ enum JsonTokens {
     Ddot,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum JsonValue {
     StringValue(String),
     IntegerValue(i64),
@@ -28,14 +30,100 @@ pub enum JsonValue {
     ArrayValue(Vec<JsonValue>)
 }
 
-pub fn parse_json(json_string: String) -> Option<JsonValue> {
-    let tokens = tokenize_json(json_string).ok()?;
-    return parse_object(&tokens, 0).map(|s| s.0);
+impl JsonValue {
+    pub fn object(pairs: Vec<(&str, JsonValue)>) -> Self {
+        return JsonValue::ObjectValue(pairs.into_iter().map(|(k, v)| (k.to_owned(), v)).collect());
+    }
+
+    pub fn string(value: &str) -> Self {
+        return JsonValue::StringValue(value.to_owned());
+    }
+
+    /// Member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        return match self {
+            JsonValue::ObjectValue(pairs) => pairs.get(key),
+            _ => None,
+        };
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        return match self {
+            JsonValue::StringValue(s) => Some(s),
+            _ => None,
+        };
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        return match self {
+            JsonValue::IntegerValue(i) => Some(*i),
+            _ => None,
+        };
+    }
 }
 
-fn parse_pair(tokens_given: &Vec<JsonTokens>, index: usize) -> Option<(String, JsonValue, usize)> {
-    if let JsonTokens::StringVal(n) = &tokens_given[index] {
-        if let JsonTokens::Ddot = tokens_given[index + 1] {
+/// Compact JSON, with object keys sorted so output is stable.
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            JsonValue::StringValue(s) => write_json_string(f, s),
+            JsonValue::IntegerValue(i) => write!(f, "{}", i),
+            JsonValue::DecimalValue(d) if d.is_finite() => write!(f, "{}", d),
+            JsonValue::DecimalValue(_) | JsonValue::Null => write!(f, "null"),
+            JsonValue::BooleanValue(b) => write!(f, "{}", b),
+            JsonValue::ArrayValue(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            JsonValue::ObjectValue(pairs) => {
+                let mut keys = pairs.keys().collect::<Vec<_>>();
+                keys.sort();
+                write!(f, "{{")?;
+                for (i, key) in keys.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{}", pairs[key])?;
+                }
+                write!(f, "}}")
+            }
+        };
+    }
+}
+
+fn write_json_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    return write!(f, "\"");
+}
+
+pub fn parse_json(json_string: String) -> Result<JsonValue, Error> {
+    let tokens = tokenize_json(json_string).map_err(Error::Parse)?;
+    return parse_object(&tokens, 0)
+        .map(|s| s.0)
+        .ok_or_else(|| Error::Parse("Invalid json document".to_owned()));
+}
+
+fn parse_pair(tokens_given: &[JsonTokens], index: usize) -> Option<(String, JsonValue, usize)> {
+    if let JsonTokens::StringVal(n) = tokens_given.get(index)? {
+        if let JsonTokens::Ddot = *tokens_given.get(index + 1)? {
             let (val, n_index) = parse_value(tokens_given, index + 2)?;
             return Some((n.to_owned(), val, n_index));
         }
@@ -44,7 +132,7 @@ fn parse_pair(tokens_given: &Vec<JsonTokens>, index: usize) -> Option<(String, J
     return None;
 }
 
-fn parse_value(tokens_given: &Vec<JsonTokens>, index: usize)  -> Option<(JsonValue, usize)> {
+fn parse_value(tokens_given: &[JsonTokens], index: usize)  -> Option<(JsonValue, usize)> {
     return parse_string(tokens_given, index)
         .or_else(|| parse_decimal(tokens_given, index))
         .or_else(|| parse_null(tokens_given, index))
@@ -54,18 +142,18 @@ fn parse_value(tokens_given: &Vec<JsonTokens>, index: usize)  -> Option<(JsonVal
         .or_else(|| parse_object(tokens_given, index));
 }
 
-fn parse_object(tokens_given: &Vec<JsonTokens>, index: usize) -> Option<(JsonValue, usize)> {
-    if let JsonTokens::OpenOb = tokens_given[index] {
+fn parse_object(tokens_given: &[JsonTokens], index: usize) -> Option<(JsonValue, usize)> {
+    if let JsonTokens::OpenOb = *tokens_given.get(index)? {
         let mut n_index = index + 1;
         let mut ress = HashMap::new();
-        if tokens_given[n_index] != JsonTokens::CloseOb {
+        if *tokens_given.get(n_index)? != JsonTokens::CloseOb {
             loop {
                 let (name, val, ret_index) = parse_pair(tokens_given, n_index)?;
                 n_index = ret_index;
                 ress.insert(name, val);
-                if tokens_given[n_index] == JsonTokens::CloseOb {
+                if *tokens_given.get(n_index)? == JsonTokens::CloseOb {
                     break;
-                } else if tokens_given[n_index] == JsonTokens::Separator {
+                } else if *tokens_given.get(n_index)? == JsonTokens::Separator {
                     n_index += 1;
                     continue;
                 } else {
@@ -78,18 +166,18 @@ fn parse_object(tokens_given: &Vec<JsonTokens>, index: usize) -> Option<(JsonVal
     return None;
 }
 
-fn parse_array(tokens_given: &Vec<JsonTokens>, index: usize)  -> Option<(JsonValue, usize)> {
-    if let JsonTokens::OpArr = tokens_given[index] {
+fn parse_array(tokens_given: &[JsonTokens], index: usize)  -> Option<(JsonValue, usize)> {
+    if let JsonTokens::OpArr = *tokens_given.get(index)? {
         let mut n_index = index + 1;
         let mut ress = Vec::new();
-        if tokens_given[n_index] != JsonTokens::CloseArr {
+        if *tokens_given.get(n_index)? != JsonTokens::CloseArr {
             loop {
                 let (ret, ret_index) = parse_value(tokens_given, n_index)?;
                 n_index = ret_index;
                 ress.push(ret);
-                if tokens_given[n_index] == JsonTokens::CloseArr {
+                if *tokens_given.get(n_index)? == JsonTokens::CloseArr {
                     break;
-                } else if tokens_given[n_index] == JsonTokens::Separator {
+                } else if *tokens_given.get(n_index)? == JsonTokens::Separator {
                     n_index += 1;
                     continue;
                 } else {
@@ -102,37 +190,37 @@ fn parse_array(tokens_given: &Vec<JsonTokens>, index: usize)  -> Option<(JsonVal
     return None;
 }
 
-fn parse_string(tokens_given: &Vec<JsonTokens>, index: usize) -> Option<(JsonValue, usize)> {
-    if let JsonTokens::StringVal(v) = &tokens_given[index] {
+fn parse_string(tokens_given: &[JsonTokens], index: usize) -> Option<(JsonValue, usize)> {
+    if let JsonTokens::StringVal(v) = tokens_given.get(index)? {
         return Some((JsonValue::StringValue(v.to_owned()), index + 1));
     }
 
     return None;
 }
 
-fn parse_decimal(tokens_given: &Vec<JsonTokens>, index: usize) -> Option<(JsonValue, usize)> {
-    if let JsonTokens::DecVal(v) = tokens_given[index] {
+fn parse_decimal(tokens_given: &[JsonTokens], index: usize) -> Option<(JsonValue, usize)> {
+    if let JsonTokens::DecVal(v) = *tokens_given.get(index)? {
         return Some((JsonValue::DecimalValue(v), index + 1));
     }
     return None;
 }
 
-fn parse_interger(tokens_given: &Vec<JsonTokens>, index: usize) -> Option<(JsonValue, usize)> {
-    if let JsonTokens::NumVal(v) = tokens_given[index] {
+fn parse_interger(tokens_given: &[JsonTokens], index: usize) -> Option<(JsonValue, usize)> {
+    if let JsonTokens::NumVal(v) = *tokens_given.get(index)? {
         return Some((JsonValue::IntegerValue(v), index + 1));
     }
     return None;
 }
 
-fn parse_null(tokens_given: &Vec<JsonTokens>, index: usize) -> Option<(JsonValue, usize)> {
-    if let JsonTokens::Null = tokens_given[index] {
+fn parse_null(tokens_given: &[JsonTokens], index: usize) -> Option<(JsonValue, usize)> {
+    if let JsonTokens::Null = *tokens_given.get(index)? {
         return Some((JsonValue::Null, index + 1));
     }
     return None;
 }
 
-fn parse_boolean(tokens_given: &Vec<JsonTokens>, index: usize) -> Option<(JsonValue, usize)> {
-    if let JsonTokens::BoolVal(s) = tokens_given[index] {
+fn parse_boolean(tokens_given: &[JsonTokens], index: usize) -> Option<(JsonValue, usize)> {
+    if let JsonTokens::BoolVal(s) = *tokens_given.get(index)? {
         return Some((JsonValue::BooleanValue(s), index + 1));
     }
     return None;
@@ -181,7 +269,7 @@ fn parse_number(start: char, supplier: &mut Peekable<Chars>) -> Result<JsonToken
         }
     }
 
-    let string_num = vec![start].iter().chain(res.iter()).collect::<String>();
+    let string_num = [start].iter().chain(res.iter()).collect::<String>();
 
     if let Ok(num) = string_num.parse::<i64>() {
         return Ok(JsonTokens::NumVal(num));
@@ -194,51 +282,27 @@ fn parse_number(start: char, supplier: &mut Peekable<Chars>) -> Result<JsonToken
 }
 
 fn parse_str(supplier: &mut Peekable<Chars>) -> Result<JsonTokens, String> {
-    let mut res = Vec::new();
-    let mut prev = '"';
+    let mut res = String::new();
+    let mut escaped = false;
+    let mut closed = false;
     for c in supplier {
-        if c == '"' && prev != '\\' {
-            prev = c;
+        if c == '"' && !escaped {
+            closed = true;
             break;
         }
-        res.push(c as u8);
-        prev = c;
+        escaped = c == '\\' && !escaped;
+        res.push(c);
     }
 
-    if prev == '"' {
-        return Ok(JsonTokens::StringVal(unescape(res.as_slice()).unwrap()));
+    if closed {
+        return Ok(JsonTokens::StringVal(escape::unescape(&res).into_owned()));
     }
 
     return Err("Unfinished json string literal".to_owned());
 }
 
-fn unescape(s: &[u8]) -> Result<String, Box<dyn Error>> {
-    let mut output = Vec::new();
-    let mut i = 0;
-
-    while i < s.len() {
-        match s[i] {
-            b'\\' => {
-                i += 1;
-                match s[i] {
-                    b'u' => {
-                        let num = u8::from_str_radix(std::str::from_utf8(&s[i+1..][..4])?, 16)?;
-                        output.push(num);
-                        i += 4;
-                    }
-                    byte => output.push(byte),
-                }
-            },
-            byte => output.push(byte),
-        }
-        i += 1;
-    }
-    Ok(String::from_utf8(output)?)
-}
-
-
 fn parse_bool(started: char, supplier: &mut Peekable<Chars>) -> Result<JsonTokens, String> {
-    let result = vec![supplier.next(), supplier.next(), supplier.next()]
+    let result = [supplier.next(), supplier.next(), supplier.next()]
         .iter()
         .filter_map(|c| *c)
         .collect::<String>();
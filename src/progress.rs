@@ -0,0 +1,154 @@
+//! Progress of indexing a folder: a status line with rates and an ETA while
+//! it runs, and a summary at the end.
+
+use std::{
+    io::IsTerminal,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::log::{self, info, Level};
+
+const REDRAW_EVERY: Duration = Duration::from_millis(100);
+
+pub struct Progress {
+    started: Instant,
+    /// When the status line was last drawn, `None` if it isn't shown.
+    drawn: Option<Instant>,
+    /// Matching files and their bytes under the root, counted up front.
+    total_files: usize,
+    total_bytes: u64,
+    /// Files tried so far, indexed or not, and their bytes.
+    done_files: usize,
+    done_bytes: u64,
+    pub indexed: usize,
+    indexed_bytes: u64,
+    /// Files left out for their extension.
+    pub ignored: usize,
+}
+
+impl Progress {
+    /// Shows a status line at the normal level when stderr is a terminal,
+    /// which takes counting the files first.
+    pub fn new(root: &Path, extensions: &[String]) -> Self {
+        let shown = log::level() == Level::Normal && std::io::stderr().is_terminal();
+        let (total_files, total_bytes) = if shown { count_files(root, extensions) } else { (0, 0) };
+        let started = Instant::now();
+        return Self {
+            started,
+            drawn: if shown { started.checked_sub(REDRAW_EVERY) } else { None },
+            total_files,
+            total_bytes,
+            done_files: 0,
+            done_bytes: 0,
+            indexed: 0,
+            indexed_bytes: 0,
+            ignored: 0,
+        };
+    }
+
+    /// Counts a file of `size` bytes as done, indexed or failed.
+    pub fn file_done(&mut self, size: u64, indexed: bool) {
+        self.done_files += 1;
+        self.done_bytes += size;
+        if indexed {
+            self.indexed += 1;
+            self.indexed_bytes += size;
+        }
+
+        match self.drawn {
+            Some(drawn) if drawn.elapsed() >= REDRAW_EVERY => {
+                log::status(Some(self.status_line()));
+                self.drawn = Some(Instant::now());
+            }
+            _ => {}
+        }
+    }
+
+    fn status_line(&self) -> String {
+        let seconds = self.started.elapsed().as_secs_f64().max(0.001);
+        let bytes_per_second = self.done_bytes as f64 / seconds;
+        let eta = if bytes_per_second > 0.0 {
+            let left = self.total_bytes.saturating_sub(self.done_bytes) as f64 / bytes_per_second;
+            format_duration(Duration::from_secs_f64(left))
+        } else {
+            "?".to_owned()
+        };
+        return format!(
+            "Indexing {}/{} files, {}/{}, {:.0} files/s, {}/s, ETA {}",
+            self.done_files,
+            self.total_files,
+            format_bytes(self.done_bytes as f64),
+            format_bytes(self.total_bytes as f64),
+            self.done_files as f64 / seconds,
+            format_bytes(bytes_per_second),
+            eta,
+        );
+    }
+
+    /// Sums up, `binary` being the files left out for looking binary and
+    /// `failed` the files and folders that couldn't be read.
+    pub fn finish(&self, binary: usize, failed: usize) {
+        info!(
+            "Indexed {} files ({}) in {}, skipped {} with other extensions and {} binary, {} failed",
+            self.indexed,
+            format_bytes(self.indexed_bytes as f64),
+            format_duration(self.started.elapsed()),
+            self.ignored,
+            binary,
+            failed,
+        );
+    }
+}
+
+/// Files under `root` with one of `extensions`, and their bytes. Whatever
+/// can't be read is left for indexing to report.
+fn count_files(root: &Path, extensions: &[String]) -> (usize, u64) {
+    let mut totals = (0, 0);
+    for entry in root.read_dir().into_iter().flatten().flatten() {
+        let path = entry.path();
+        match entry.metadata() {
+            Ok(m) if m.is_dir() => {
+                let (files, bytes) = count_files(&path, extensions);
+                totals.0 += files;
+                totals.1 += bytes;
+            }
+            Ok(m) if m.is_file() && has_extension(&path, extensions) => {
+                totals.0 += 1;
+                totals.1 += m.len();
+            }
+            _ => {}
+        }
+    }
+    return totals;
+}
+
+pub fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    return path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| extensions.iter().any(|x| x == e))
+        .unwrap_or(false);
+}
+
+fn format_bytes(bytes: f64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    return match unit {
+        0 => format!("{:.0} {}", size, units[unit]),
+        _ => format!("{:.1} {}", size, units[unit]),
+    };
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    return match seconds {
+        0 => format!("{}ms", duration.as_millis()),
+        1..=59 => format!("{:.1}s", duration.as_secs_f64()),
+        _ => format!("{}m{:02}s", seconds / 60, seconds % 60),
+    };
+}
@@ -0,0 +1,748 @@
+//! Small regular expression engine used for `/regex/` queries.
+//!
+//! Supports literals, `.`, classes (`[a-z]`, `[^...]`, `\d \w \s` and their
+//! negations), anchors (`^ $ \b \B`, line based), groups, alternation and the
+//! `* + ? {n} {n,} {n,m}` quantifiers, greedy or lazy. Patterns compile to a
+//! program run by a Pike VM, so matching is linear in the input.
+
+use crate::error::{self, Error};
+
+const MAX_REPEAT: u32 = 1000;
+/// Most instructions a pattern may compile to. Counted repeats are unrolled,
+/// so nesting them multiplies.
+const MAX_PROGRAM: u64 = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(&self, c: char) -> bool {
+        return match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => is_word_char(c),
+            Perl::Space => c.is_whitespace(),
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+    case_insensitive: bool,
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        let hit = if self.case_insensitive {
+            c.to_lowercase().chain(c.to_uppercase()).any(|v| self.matches_exact(v))
+        } else {
+            self.matches_exact(c)
+        };
+        return hit != self.negated;
+    }
+
+    fn matches_exact(&self, c: char) -> bool {
+        return self.items.iter().any(|item| match item {
+            ClassItem::Range(from, to) => *from <= c && c <= *to,
+            ClassItem::Perl(perl, negated) => perl.matches(c) != *negated,
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    LineStart,
+    LineEnd,
+    WordBoundary(bool),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat { node: Box<Node>, min: u32, max: Option<u32>, greedy: bool },
+}
+
+/// What a document has to contain for the pattern to possibly match,
+/// expressed as literal strings. Used to pick candidates from the trigram
+/// index before running the regex on file contents.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Requirement {
+    Anything,
+    Literal(String),
+    All(Vec<Requirement>),
+    Any(Vec<Requirement>),
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    LineStart,
+    LineEnd,
+    WordBoundary(bool),
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    ast: Node,
+    program: Vec<Inst>,
+}
+
+#[allow(dead_code)]
+impl Regex {
+
+    pub fn new(pattern: &str) -> error::Result<Self> {
+        return Self::with_case(pattern, false);
+    }
+
+    pub fn with_case(pattern: &str, case_insensitive: bool) -> error::Result<Self> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            case_insensitive,
+        };
+        let ast = parser.parse_alt().map_err(Error::Parse)?;
+        if parser.pos < parser.chars.len() {
+            return Err(Error::Parse(format!("Unmatched ')' at {} in regex", parser.pos)));
+        }
+
+        if program_size(&ast) > MAX_PROGRAM {
+            return Err(Error::Parse(format!("Regex is too large, it would compile to over {} instructions", MAX_PROGRAM)));
+        }
+        let mut program = Vec::new();
+        compile(&ast, case_insensitive, &mut program);
+        program.push(Inst::Match);
+
+        return Ok(Self { ast, program });
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        return self.find_at(text, 0).is_some();
+    }
+
+    /// Leftmost match as a byte range.
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        return self.find_at(text, 0);
+    }
+
+    /// All non-overlapping matches as byte ranges.
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> impl Iterator<Item = (usize, usize)> + 'r
+    where 't: 'r {
+        let mut at = 0;
+        return std::iter::from_fn(move || {
+            if at > text.len() {
+                return None;
+            }
+            let (start, end) = self.find_at(text, at)?;
+            at = if end > start {
+                end
+            } else {
+                end + text[end..].chars().next().map(|c| c.len_utf8()).unwrap_or(1)
+            };
+            return Some((start, end));
+        });
+    }
+
+    pub fn requirement(&self) -> Requirement {
+        return simplify(requirement_of(&self.ast));
+    }
+
+    fn find_at(&self, text: &str, from: usize) -> Option<(usize, usize)> {
+        let mut current: Vec<(usize, usize)> = Vec::new();
+        let mut next: Vec<(usize, usize)> = Vec::new();
+        let mut seen = vec![usize::MAX; self.program.len()];
+        let mut matched = None;
+
+        let mut prev = text[..from].chars().next_back();
+        let mut chars = text[from..].char_indices().map(|(i, c)| (i + from, c)).peekable();
+        let mut pos = from;
+        let mut step = 0;
+
+        loop {
+            let next_char = chars.peek().map(|(_, c)| *c);
+            let context = Context { prev, next: next_char };
+
+            if matched.is_none() {
+                self.add_thread(&mut current, &mut seen, step, 0, pos, &context);
+            }
+            if current.is_empty() && matched.is_some() {
+                break;
+            }
+
+            step += 1;
+            for &(pc, start) in current.iter() {
+                let hit = match (&self.program[pc], next_char) {
+                    (Inst::Match, _) => {
+                        matched = Some((start, pos));
+                        break;
+                    }
+                    (Inst::Char(expected), Some(c)) => *expected == c,
+                    (Inst::Any, Some(c)) => c != '\n',
+                    (Inst::Class(class), Some(c)) => class.matches(c),
+                    _ => false,
+                };
+                if hit {
+                    let c = next_char.unwrap();
+                    let after = Context {
+                        prev: Some(c),
+                        next: text[pos + c.len_utf8()..].chars().next(),
+                    };
+                    self.add_thread(&mut next, &mut seen, step, pc + 1, start, &after);
+                }
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+
+            match chars.next() {
+                Some((_, c)) => {
+                    prev = Some(c);
+                    pos += c.len_utf8();
+                }
+                None => break,
+            }
+        }
+
+        return matched;
+    }
+
+    fn add_thread(&self, list: &mut Vec<(usize, usize)>, seen: &mut [usize], step: usize, pc: usize, start: usize, context: &Context) {
+        if seen[pc] == step {
+            return;
+        }
+        seen[pc] = step;
+
+        match &self.program[pc] {
+            Inst::Jmp(to) => self.add_thread(list, seen, step, *to, start, context),
+            Inst::Split(first, second) => {
+                self.add_thread(list, seen, step, *first, start, context);
+                self.add_thread(list, seen, step, *second, start, context);
+            }
+            Inst::LineStart => if context.prev.map(|c| c == '\n').unwrap_or(true) {
+                self.add_thread(list, seen, step, pc + 1, start, context);
+            }
+            Inst::LineEnd => if context.next.map(|c| c == '\n').unwrap_or(true) {
+                self.add_thread(list, seen, step, pc + 1, start, context);
+            }
+            Inst::WordBoundary(expected) => {
+                let at_boundary = context.prev.map(is_word_char).unwrap_or(false)
+                    != context.next.map(is_word_char).unwrap_or(false);
+                if at_boundary == *expected {
+                    self.add_thread(list, seen, step, pc + 1, start, context);
+                }
+            }
+            _ => list.push((pc, start)),
+        }
+    }
+}
+
+struct Context {
+    prev: Option<char>,
+    next: Option<char>,
+}
+
+fn is_word_char(c: char) -> bool {
+    return c.is_alphanumeric() || c == '_';
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    case_insensitive: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        return self.chars.get(self.pos).copied();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            return true;
+        }
+        return false;
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+
+        return Ok(if branches.len() == 1 { branches.pop().unwrap() } else { Node::Alt(branches) });
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_repeats(atom)?);
+        }
+
+        return Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        });
+    }
+
+    fn parse_repeats(&mut self, mut atom: Node) -> Result<Node, String> {
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => { self.pos += 1; (0, None) }
+                Some('+') => { self.pos += 1; (1, None) }
+                Some('?') => { self.pos += 1; (0, Some(1)) }
+                Some('{') => match self.parse_counts()? {
+                    Some(counts) => counts,
+                    None => return Ok(atom),
+                },
+                _ => return Ok(atom),
+            };
+
+            if matches!(atom, Node::LineStart | Node::LineEnd | Node::WordBoundary(_)) {
+                return Err("Nothing to repeat in regex".to_owned());
+            }
+            let greedy = !self.eat('?');
+            atom = Node::Repeat { node: Box::new(atom), min, max, greedy };
+        }
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}`. A `{` not followed by a valid count
+    /// is left alone and later taken literally.
+    fn parse_counts(&mut self) -> Result<Option<(u32, Option<u32>)>, String> {
+        let rest = self.chars[self.pos..].iter().collect::<String>();
+        let close = match rest.find('}') {
+            Some(close) => close,
+            None => return Ok(None),
+        };
+
+        let body = &rest[1..close];
+        let (min, max) = match body.split_once(',') {
+            None => match body.parse::<u32>() {
+                Ok(n) => (n, Some(n)),
+                Err(_) => return Ok(None),
+            },
+            Some((min, max)) => match (min.parse::<u32>(), max) {
+                (Ok(min), "") => (min, None),
+                (Ok(min), max) => match max.parse::<u32>() {
+                    Ok(max) => (min, Some(max)),
+                    Err(_) => return Ok(None),
+                },
+                _ => return Ok(None),
+            },
+        };
+
+        if max.map(|m| m < min).unwrap_or(false) || min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            return Err(format!("Invalid repetition {{{}}} in regex", body));
+        }
+
+        self.pos += rest[..=close].chars().count();
+        return Ok(Some((min, max)));
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        let c = self.peek().ok_or("Unexpected end of regex")?;
+        self.pos += 1;
+
+        return match c {
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err("Unsupported group flag in regex".to_owned());
+                }
+                let inner = self.parse_alt()?;
+                if !self.eat(')') {
+                    return Err("Unclosed group in regex".to_owned());
+                }
+                Ok(inner)
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::LineStart),
+            '$' => Ok(Node::LineEnd),
+            '*' | '+' | '?' => Err(format!("Nothing to repeat before '{}' in regex", c)),
+            '\\' => self.parse_escape(),
+            c => Ok(Node::Char(c)),
+        };
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, String> {
+        let c = self.peek().ok_or("Trailing backslash in regex")?;
+        self.pos += 1;
+
+        let perl = |perl, negated| Node::Class(Class {
+            items: vec![ClassItem::Perl(perl, false)],
+            negated,
+            case_insensitive: false,
+        });
+
+        return Ok(match c {
+            'd' => perl(Perl::Digit, false),
+            'D' => perl(Perl::Digit, true),
+            'w' => perl(Perl::Word, false),
+            'W' => perl(Perl::Word, true),
+            's' => perl(Perl::Space, false),
+            'S' => perl(Perl::Space, true),
+            'b' => Node::WordBoundary(true),
+            'B' => Node::WordBoundary(false),
+            _ => Node::Char(self.escaped_char(c)?),
+        });
+    }
+
+    fn escaped_char(&mut self, c: char) -> Result<char, String> {
+        return Ok(match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'x' => {
+                let hex = self.chars.get(self.pos..self.pos + 2)
+                    .ok_or("Incomplete \\x escape in regex")?
+                    .iter()
+                    .collect::<String>();
+                self.pos += 2;
+                u8::from_str_radix(&hex, 16).map_err(|_| "Invalid \\x escape in regex")? as char
+            }
+            c if c.is_alphanumeric() => return Err(format!("Unknown escape \\{} in regex", c)),
+            c => c,
+        });
+    }
+
+    fn parse_class(&mut self) -> Result<Node, String> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+
+        loop {
+            let c = self.peek().ok_or("Unclosed character class in regex")?;
+            self.pos += 1;
+
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let from = if c == '\\' {
+                let escaped = self.peek().ok_or("Trailing backslash in regex")?;
+                self.pos += 1;
+                match escaped {
+                    'd' | 'D' | 'w' | 'W' | 's' | 'S' => {
+                        let perl = match escaped.to_ascii_lowercase() {
+                            'd' => Perl::Digit,
+                            'w' => Perl::Word,
+                            _ => Perl::Space,
+                        };
+                        items.push(ClassItem::Perl(perl, escaped.is_uppercase()));
+                        continue;
+                    }
+                    other => self.escaped_char(other)?,
+                }
+            } else {
+                c
+            };
+
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).map(|c| *c != ']').unwrap_or(false) {
+                self.pos += 1;
+                let mut to = self.peek().ok_or("Unclosed character class in regex")?;
+                self.pos += 1;
+                if to == '\\' {
+                    let escaped = self.peek().ok_or("Trailing backslash in regex")?;
+                    self.pos += 1;
+                    to = self.escaped_char(escaped)?;
+                }
+                if to < from {
+                    return Err(format!("Invalid class range {}-{} in regex", from, to));
+                }
+                items.push(ClassItem::Range(from, to));
+            } else {
+                items.push(ClassItem::Range(from, from));
+            }
+        }
+
+        return Ok(Node::Class(Class { items, negated, case_insensitive: self.case_insensitive }));
+    }
+}
+
+fn compile(node: &Node, case_insensitive: bool, program: &mut Vec<Inst>) {
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => {
+            let has_case = c.to_lowercase().ne(c.to_uppercase());
+            if case_insensitive && has_case {
+                program.push(Inst::Class(Class {
+                    items: vec![ClassItem::Range(*c, *c)],
+                    negated: false,
+                    case_insensitive: true,
+                }));
+            } else {
+                program.push(Inst::Char(*c));
+            }
+        }
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::LineStart => program.push(Inst::LineStart),
+        Node::LineEnd => program.push(Inst::LineEnd),
+        Node::WordBoundary(b) => program.push(Inst::WordBoundary(*b)),
+        Node::Concat(nodes) => {
+            for n in nodes {
+                compile(n, case_insensitive, program);
+            }
+        }
+        Node::Alt(branches) => compile_alt(branches, case_insensitive, program),
+        Node::Repeat { node, min, max, greedy } => {
+            for _ in 0..*min {
+                compile(node, case_insensitive, program);
+            }
+
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(0, 0));
+                    compile(node, case_insensitive, program);
+                    program.push(Inst::Jmp(split));
+                    program[split] = ordered_split(split + 1, program.len(), *greedy);
+                }
+                Some(max) => {
+                    for _ in *min..*max {
+                        let split = program.len();
+                        program.push(Inst::Split(0, 0));
+                        compile(node, case_insensitive, program);
+                        program[split] = ordered_split(split + 1, program.len(), *greedy);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How many instructions `compile` emits for `node`, saturating.
+fn program_size(node: &Node) -> u64 {
+    return match node {
+        Node::Empty => 0,
+        Node::Concat(nodes) => nodes.iter().fold(0, |sum, n| sum.saturating_add(program_size(n))),
+        Node::Alt(branches) => branches.iter()
+            .fold(2 * (branches.len() as u64 - 1), |sum, n| sum.saturating_add(program_size(n))),
+        Node::Repeat { node, min, max, .. } => {
+            let body = program_size(node);
+            let optional = match max {
+                None => body.saturating_add(2),
+                Some(max) => (body + 1).saturating_mul((*max - *min) as u64),
+            };
+            body.saturating_mul(*min as u64).saturating_add(optional)
+        }
+        _ => 1,
+    };
+}
+
+fn compile_alt(branches: &[Node], case_insensitive: bool, program: &mut Vec<Inst>) {
+    if branches.len() == 1 {
+        compile(&branches[0], case_insensitive, program);
+        return;
+    }
+
+    let split = program.len();
+    program.push(Inst::Split(0, 0));
+    compile(&branches[0], case_insensitive, program);
+    let jump = program.len();
+    program.push(Inst::Jmp(0));
+
+    let second = program.len();
+    compile_alt(&branches[1..], case_insensitive, program);
+
+    program[split] = Inst::Split(split + 1, second);
+    program[jump] = Inst::Jmp(program.len());
+}
+
+fn ordered_split(body: usize, after: usize, greedy: bool) -> Inst {
+    return if greedy { Inst::Split(body, after) } else { Inst::Split(after, body) };
+}
+
+fn requirement_of(node: &Node) -> Requirement {
+    return match node {
+        Node::Char(c) => Requirement::Literal(c.to_lowercase().collect()),
+        Node::Concat(nodes) => {
+            let mut parts = Vec::new();
+            let mut run = String::new();
+            for n in nodes {
+                match n {
+                    Node::Char(c) => run.extend(c.to_lowercase()),
+                    // zero width, doesn't break a literal run
+                    Node::LineStart | Node::LineEnd | Node::WordBoundary(_) => {}
+                    other => {
+                        if !run.is_empty() {
+                            parts.push(Requirement::Literal(std::mem::take(&mut run)));
+                        }
+                        parts.push(requirement_of(other));
+                    }
+                }
+            }
+            if !run.is_empty() {
+                parts.push(Requirement::Literal(run));
+            }
+            Requirement::All(parts)
+        }
+        Node::Alt(branches) => Requirement::Any(branches.iter().map(requirement_of).collect()),
+        Node::Repeat { node, min, .. } if *min > 0 => requirement_of(node),
+        _ => Requirement::Anything,
+    };
+}
+
+fn simplify(requirement: Requirement) -> Requirement {
+    return match requirement {
+        Requirement::All(parts) => {
+            let mut parts = parts.into_iter()
+                .map(simplify)
+                .filter(|p| *p != Requirement::Anything)
+                .collect::<Vec<_>>();
+            match parts.len() {
+                0 => Requirement::Anything,
+                1 => parts.pop().unwrap(),
+                _ => Requirement::All(parts),
+            }
+        }
+        Requirement::Any(parts) => {
+            let mut parts = parts.into_iter().map(simplify).collect::<Vec<_>>();
+            if parts.contains(&Requirement::Anything) {
+                Requirement::Anything
+            } else if parts.len() == 1 {
+                parts.pop().unwrap()
+            } else {
+                Requirement::Any(parts)
+            }
+        }
+        other => other,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> Vec<String> {
+        let regex = Regex::new(pattern).unwrap();
+        return regex.find_iter(text).map(|(start, end)| text[start..end].to_owned()).collect();
+    }
+
+    /// Whether `text` contains what `requirement` asks for, which is what
+    /// the trigram index approximates from above.
+    fn satisfies(requirement: &Requirement, text: &str) -> bool {
+        return match requirement {
+            Requirement::Anything => true,
+            Requirement::Literal(literal) => text.to_lowercase().contains(literal.as_str()),
+            Requirement::All(parts) => parts.iter().all(|p| satisfies(p, text)),
+            Requirement::Any(parts) => parts.iter().any(|p| satisfies(p, text)),
+        };
+    }
+
+    #[test]
+    fn alternation() {
+        assert_eq!(matches("cat|dog", "hotdog catalog"), ["dog", "cat"]);
+        assert_eq!(matches("a(b|cd)e", "abe ace acde"), ["abe", "acde"]);
+        assert_eq!(matches("x(|y)z", "xz xyz"), ["xz", "xyz"]);
+    }
+
+    #[test]
+    fn classes() {
+        assert_eq!(matches("[a-c]+", "abcdcba"), ["abc", "cba"]);
+        assert_eq!(matches("[^0-9 ]+", "ab12 cd3"), ["ab", "cd"]);
+        assert_eq!(matches(r"\d+", "v1.25"), ["1", "25"]);
+        assert_eq!(matches(r"[\w-]+", "foo-bar baz"), ["foo-bar", "baz"]);
+        assert_eq!(matches(r"\S+", " a  bc "), ["a", "bc"]);
+        assert_eq!(matches("[]a]", "]a"), ["]", "a"]);
+        assert!(Regex::with_case("[a-c]", true).unwrap().is_match("B"));
+        assert!(!Regex::with_case("[^a-c]", true).unwrap().is_match("B"));
+        assert!(Regex::new("[a-").is_err());
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(matches("^a.", "ab\nac a"), ["ab", "ac"]);
+        assert_eq!(matches(".b$", "ab\ncb x"), ["ab"]);
+        assert_eq!(matches(r"\bis\b", "this is it"), ["is"]);
+        assert_eq!(matches(r"\Bis\b", "this is it"), ["is"]);
+        assert_eq!(Regex::new(r"\bis").unwrap().find("this is"), Some((5, 7)));
+    }
+
+    #[test]
+    fn counted_repeats() {
+        assert_eq!(matches("a{2}", "aaaaa"), ["aa", "aa"]);
+        assert_eq!(matches("a{2,}", "a aa aaaa"), ["aa", "aaaa"]);
+        assert_eq!(matches("a{1,3}", "aaaa"), ["aaa", "a"]);
+        assert_eq!(matches("a{1,3}?", "aaa"), ["a", "a", "a"]);
+        assert_eq!(matches("(ab){2}", "ababab"), ["abab"]);
+        assert!(Regex::new("a{3,2}").is_err());
+        assert!(Regex::new("a{1001}").is_err());
+        assert!(Regex::new("a{1000}").is_ok());
+    }
+
+    #[test]
+    fn nested_repeats_are_limited() {
+        assert!(Regex::new("((a{1000}){1000}){1000}").is_err());
+        assert!(Regex::new("(a{1000}){1000}").is_err());
+        assert!(Regex::new("(a{10}){10}").is_ok());
+    }
+
+    #[test]
+    fn program_size_is_exact() {
+        for pattern in ["a", "ab|c|d", "(ab){2,5}", "x*?", "[a-z]+y", "^a{3}(b|c)?$", r"\bx{0,2}\B"] {
+            let regex = Regex::new(pattern).unwrap();
+            assert_eq!(program_size(&regex.ast), regex.program.len() as u64 - 1, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn requirement_decomposition() {
+        let literal = |s: &str| Requirement::Literal(s.to_owned());
+        assert_eq!(Regex::new("Hello").unwrap().requirement(), literal("hello"));
+        assert_eq!(
+            Regex::new("foo.*bar").unwrap().requirement(),
+            Requirement::All(vec![literal("foo"), literal("bar")]),
+        );
+        assert_eq!(
+            Regex::new("(cat|dog)s").unwrap().requirement(),
+            Requirement::All(vec![Requirement::Any(vec![literal("cat"), literal("dog")]), literal("s")]),
+        );
+        assert_eq!(Regex::new("x?abc").unwrap().requirement(), literal("abc"));
+        assert_eq!(Regex::new("a|.*").unwrap().requirement(), Requirement::Anything);
+        assert_eq!(Regex::new("(ab)+c").unwrap().requirement(), Requirement::All(vec![literal("ab"), literal("c")]));
+    }
+
+    #[test]
+    fn requirement_covers_every_match() {
+        let texts = [
+            "the quick brown fox", "foo and bar", "FOOBAR", "cats and dogs", "dog", "abcabc", "x1y22z333",
+            "line one\nline two", "", "ab ab ab c",
+        ];
+        let patterns = [
+            "quick", "foo.*bar", "(cat|dog)s?", "b.r", r"\d{2,}", "^line", "t[wh]o", "(ab )+c", "z?y",
+            "o+", "[a-c]{3}",
+        ];
+        for pattern in patterns {
+            let regex = Regex::with_case(pattern, true).unwrap();
+            let requirement = regex.requirement();
+            for text in texts {
+                if regex.is_match(text) {
+                    assert!(satisfies(&requirement, text), "{} matches {:?} but requires {:?}", pattern, text, requirement);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    documents::DocId,
+    error::{Error, Result},
+    index_files::{Corpus, Granularity},
+    search::{document_snippet, explain, file_hits, search_term},
+    snippet::{Highlight, SNIPPET_WIDTH},
+    trie::Ranking,
+};
+
+/// How many past queries `:history` keeps.
+const HISTORY_LENGTH: usize = 500;
+
+const HELP: &str = "\
+Type a query to search. Commands:
+  :more            Show the next results
+  :open N          Open result N in $VISUAL, $EDITOR or the system opener
+  :explain N       Show what each part of the query added to result N
+  :complete P      Complete the prefix P (also `P<Tab>` when your terminal sends it)
+  :history         List past queries; `!N` runs query N again, `!!` the last one
+  :help            Show this help
+  :quit            Leave (so does end of input)";
+
+pub struct Repl<'c> {
+    corpus: &'c Corpus,
+    page: usize,
+    highlight: Highlight,
+    history: Vec<String>,
+    history_file: Option<PathBuf>,
+    /// Command line `:open` runs with the path appended.
+    opener: String,
+    query: Option<String>,
+    results: Vec<(DocId, f64)>,
+    shown: usize,
+}
+
+impl <'c> Repl<'c> {
+
+    pub fn new(corpus: &'c Corpus, page: usize) -> Self {
+        let history_file = env::var_os("HOME").map(|home| Path::new(&home).join(".fji_tf_idf_history"));
+        return Self::with_history(corpus, page, history_file);
+    }
+
+    /// Keeps the history in `history_file`, or in memory only with `None`.
+    fn with_history(corpus: &'c Corpus, page: usize, history_file: Option<PathBuf>) -> Self {
+        let mut history = history_file.as_ref()
+            .and_then(|file| std::fs::read_to_string(file).ok())
+            .map(|text| text.lines().filter(|l| !l.is_empty()).map(str::to_owned).collect::<Vec<_>>())
+            .unwrap_or_default();
+        history.drain(..history.len().saturating_sub(HISTORY_LENGTH));
+
+        return Self {
+            corpus,
+            page: page.max(1),
+            highlight: if std::io::stdout().is_terminal() { Highlight::Ansi } else { Highlight::Plain },
+            history,
+            history_file,
+            opener: env::var("VISUAL").or_else(|_| env::var("EDITOR")).ok()
+                .filter(|editor| !editor.trim().is_empty())
+                .unwrap_or_else(|| if cfg!(target_os = "macos") { "open".to_owned() } else { "xdg-open".to_owned() }),
+            query: None,
+            results: Vec::new(),
+            shown: 0,
+        };
+    }
+
+    /// Reads commands from stdin until `:quit` or the end of input.
+    pub fn run(&mut self) -> Result<()> {
+        let stdin = std::io::stdin();
+        println!("{} documents. :help lists the commands.", self.corpus.documents.len());
+        prompt()?;
+        for line in stdin.lock().lines() {
+            let line = line.map_err(|e| Error::io(Path::new("<stdin>"), e))?;
+            match self.handle(line.trim_end_matches(['\r', '\n'])) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(e) => println!("error: {}", e),
+            }
+            prompt()?;
+        }
+        println!();
+        return Ok(());
+    }
+
+    /// Runs one line, `false` means quit.
+    fn handle(&mut self, line: &str) -> Result<bool> {
+        if let Some(prefix) = line.strip_suffix('\t') {
+            self.complete(prefix.split_whitespace().last().unwrap_or(""));
+            return Ok(true);
+        }
+
+        let line = line.trim();
+        let (command, argument) = line.split_once(char::is_whitespace)
+            .map(|(c, a)| (c, a.trim()))
+            .unwrap_or((line, ""));
+
+        match command {
+            "" => {}
+            ":q" | ":quit" | ":exit" => return Ok(false),
+            ":h" | ":help" => println!("{}", HELP),
+            ":m" | ":more" => self.show_page(),
+            ":o" | ":open" => self.open(self.result(argument)?)?,
+            ":e" | ":explain" => self.explain(self.result(argument)?)?,
+            ":c" | ":complete" => self.complete(argument),
+            ":history" => {
+                for (n, query) in self.history.iter().enumerate() {
+                    println!("{:>4}  {}", n + 1, query);
+                }
+            }
+            _ if command.starts_with(':') => println!("Unknown command {}, :help lists them", command),
+            _ if line.starts_with('!') => {
+                let query = match &line[1..] {
+                    "!" => self.history.last(),
+                    n => n.parse::<usize>().ok().and_then(|n| self.history.get(n.wrapping_sub(1))),
+                };
+                match query.cloned() {
+                    Some(query) => {
+                        println!("{}", query);
+                        self.search(&query)?;
+                    }
+                    None => println!("No such history entry"),
+                }
+            }
+            _ => self.search(line)?,
+        }
+        return Ok(true);
+    }
+
+    fn search(&mut self, query: &str) -> Result<()> {
+        self.remember(query);
+        let mut results = search_term(query.to_owned(), self.corpus)?;
+        results.reverse();
+
+        self.query = Some(query.to_owned());
+        self.results = results;
+        self.shown = 0;
+        if self.results.is_empty() {
+            println!("No results");
+        }
+        self.show_page();
+        return Ok(());
+    }
+
+    fn show_page(&mut self) {
+        let query = match &self.query {
+            Some(query) => query.clone(),
+            None => return println!("Search for something first"),
+        };
+        if self.shown >= self.results.len() {
+            if !self.results.is_empty() {
+                println!("No more results");
+            }
+            return;
+        }
+
+        let end = (self.shown + self.page).min(self.results.len());
+        let grouped = self.corpus.options.granularity != Granularity::File;
+        for n in self.shown..end {
+            let (id, score) = self.results[n];
+            println!("{:>3}. {} -> {:.4}", n + 1, self.corpus.documents.path(id).display(), score);
+            if let Some((_, snippet)) = document_snippet(&query, self.corpus, id, SNIPPET_WIDTH) {
+                println!("     {}", snippet.render(self.highlight));
+            }
+            if grouped {
+                for (file, hits) in file_hits(&query, self.corpus, id).unwrap_or_default() {
+                    println!("     - {} ({} hits)", file.display(), hits);
+                }
+            }
+        }
+        self.shown = end;
+        if end < self.results.len() {
+            println!("{} of {} shown, :more for the next {}", end, self.results.len(), self.page);
+        }
+    }
+
+    /// The document of the 1-based result number `argument`.
+    fn result(&self, argument: &str) -> Result<DocId> {
+        let n = argument.parse::<usize>()
+            .map_err(|_| Error::Usage(format!("Expected a result number, got {:?}", argument)))?;
+        return self.results.get(n.wrapping_sub(1))
+            .map(|(id, _)| *id)
+            .ok_or_else(|| Error::Usage(format!("There is no result {}", n)));
+    }
+
+    /// Opens the document, or for a grouped one the file with the most hits.
+    fn open(&self, id: DocId) -> Result<()> {
+        let info = match self.corpus.documents.get(id) {
+            Some(info) => info,
+            None => return Ok(()),
+        };
+        let best = self.query.as_deref()
+            .and_then(|query| file_hits(query, self.corpus, id).ok())
+            .and_then(|files| files.into_iter().next())
+            .map(|(file, _)| file);
+        let path = best.or_else(|| info.files.first().map(|f| f.path.clone())).unwrap_or_else(|| info.path.clone());
+
+        let mut words = self.opener.split_whitespace();
+        let program = words.next().unwrap_or("xdg-open");
+
+        let status = Command::new(program).args(words).arg(&path).status()
+            .map_err(|e| Error::io(Path::new(program), e))?;
+        if !status.success() {
+            println!("{} exited with {}", program, status);
+        }
+        return Ok(());
+    }
+
+    fn explain(&self, id: DocId) -> Result<()> {
+        print!("{}", explain(self.query.as_deref().unwrap_or(""), self.corpus, id)?);
+        return Ok(());
+    }
+
+    fn complete(&self, prefix: &str) {
+        let completions = self.corpus.triee.complete(&prefix.to_lowercase(), self.page, Ranking::DocumentFrequency);
+        if completions.is_empty() {
+            println!("No completions for {:?}", prefix);
+        }
+        for (word, score) in completions {
+            println!("  {} ({})", word, score);
+        }
+    }
+
+    /// Adds a query to the history, in memory and in the history file. The
+    /// file is best effort, a read-only home shouldn't stop searching.
+    fn remember(&mut self, query: &str) {
+        if self.history.last().map(String::as_str) == Some(query) {
+            return;
+        }
+        self.history.push(query.to_owned());
+        if self.history.len() > HISTORY_LENGTH {
+            self.history.drain(..self.history.len() - HISTORY_LENGTH);
+        }
+
+        if let Some(file) = &self.history_file {
+            if let Ok(mut out) = OpenOptions::new().create(true).append(true).open(file) {
+                let _ = writeln!(out, "{}", query);
+            }
+        }
+    }
+}
+
+fn prompt() -> Result<()> {
+    print!("search> ");
+    return std::io::stdout().flush().map_err(|e| Error::io(Path::new("<stdout>"), e));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{index_files::IndexOptions, testing::indexed};
+
+    const FILES: &[(&str, &[u8])] = &[
+        ("1.md", b"alpha alpha alpha alpha alpha"),
+        ("2.md", b"alpha alpha alpha alpha"),
+        ("3.md", b"alpha alpha alpha beta"),
+        ("4.md", b"alpha alpha beta"),
+        ("5.md", b"alpha gamma"),
+        ("6.md", b"delta"),
+    ];
+
+    fn name(repl: &Repl, n: usize) -> String {
+        let (id, _) = repl.results[n];
+        return repl.corpus.documents.path(id).file_name().unwrap().to_string_lossy().into_owned();
+    }
+
+    #[test]
+    fn results_page_with_more() {
+        let (_tree, corpus) = indexed("repl-pages", FILES, IndexOptions::new(&["md"]));
+        let mut repl = Repl::with_history(&corpus, 2, None);
+
+        assert!(repl.handle(":more").unwrap());
+        assert!(repl.handle("alpha").unwrap());
+        assert_eq!(repl.results.len(), 5);
+        assert_eq!((name(&repl, 0), name(&repl, 4)), ("1.md".to_owned(), "5.md".to_owned()));
+        assert_eq!(repl.shown, 2);
+        for shown in [4, 5, 5] {
+            repl.handle(":m").unwrap();
+            assert_eq!(repl.shown, shown);
+        }
+
+        repl.handle("zzzz").unwrap();
+        assert!(repl.results.is_empty());
+        assert_eq!(repl.shown, 0);
+        assert!(!repl.handle(":quit").unwrap());
+    }
+
+    #[test]
+    fn history_is_kept_in_its_file() {
+        let (tree, corpus) = indexed("repl-history", FILES, IndexOptions::new(&["md"]));
+        let file = tree.root.join("history");
+        let old = (0..HISTORY_LENGTH + 10).map(|n| format!("old {}\n\n", n)).collect::<String>();
+        std::fs::write(&file, old).unwrap();
+
+        let mut repl = Repl::with_history(&corpus, 2, Some(file.clone()));
+        assert_eq!(repl.history.len(), HISTORY_LENGTH);
+        assert_eq!(repl.history[0], "old 10");
+        repl.history.clear();
+
+        for line in ["alpha", "alpha", "beta", "!1", "!!", "!9", "!x"] {
+            repl.handle(line).unwrap();
+        }
+        assert_eq!(repl.history, ["alpha", "beta", "alpha"]);
+        assert_eq!(repl.query.as_deref(), Some("alpha"));
+        let saved = std::fs::read_to_string(&file).unwrap();
+        assert!(saved.ends_with("old 509\n\nalpha\nbeta\nalpha\n"));
+
+        let reloaded = Repl::with_history(&corpus, 2, Some(file));
+        assert_eq!(reloaded.history[HISTORY_LENGTH - 3..], ["alpha", "beta", "alpha"]);
+    }
+
+    #[test]
+    fn result_numbers_are_checked() {
+        let (_tree, corpus) = indexed("repl-numbers", FILES, IndexOptions::new(&["md"]));
+        let mut repl = Repl::with_history(&corpus, 2, None);
+        repl.handle("beta").unwrap();
+
+        assert!(repl.result("1").is_ok());
+        for bad in ["0", "3", "x", ""] {
+            assert!(matches!(repl.handle(&format!(":open {}", bad)), Err(Error::Usage(_))), "{}", bad);
+            assert!(matches!(repl.handle(&format!(":explain {}", bad)), Err(Error::Usage(_))), "{}", bad);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn open_runs_the_opener_on_the_best_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut options = IndexOptions::new(&["md"]);
+        options.granularity = Granularity::Directory;
+        let (tree, corpus) = indexed("repl-open", &[
+            ("a/1.md", b"beta"),
+            ("a/2.md", b"gamma gamma"),
+            ("b/3.md", b"gamma"),
+        ], options);
+        let opened = tree.root.join("opened");
+        let script = tree.root.join("open.sh");
+        std::fs::write(&script, format!("#!/bin/sh\necho \"$1\" > {}\n", opened.display())).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut repl = Repl::with_history(&corpus, 2, None);
+        repl.opener = script.display().to_string();
+        repl.handle("gamma").unwrap();
+        assert_eq!(name(&repl, 0), "a");
+        repl.handle(":open 1").unwrap();
+
+        let path = std::fs::read_to_string(&opened).unwrap();
+        assert_eq!(path.trim_end(), tree.root.join("a/2.md").display().to_string());
+    }
+}
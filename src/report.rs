@@ -0,0 +1,253 @@
+//! What the CLI's `--format json` and the HTTP server answer with, built as
+//! `JsonValue`s so both give the same shapes.
+
+use std::{collections::BTreeMap, path::Path};
+
+use crate::{
+    documents::DocId,
+    index_files::{Corpus, Granularity},
+    parse::JsonValue,
+    search::{self, document_snippet, file_hits},
+    snippet::SNIPPET_WIDTH,
+    trie::Ranking,
+};
+
+/// Id, path, title and length of a document.
+pub fn document(corpus: &Corpus, id: DocId) -> JsonValue {
+    let info = corpus.documents.get(id);
+    return JsonValue::object(vec![
+        ("id", JsonValue::IntegerValue(id as i64)),
+        ("path", info.map(|i| path(&i.path)).unwrap_or(JsonValue::Null)),
+        ("title", info.and_then(|i| i.title.as_deref()).map(JsonValue::string).unwrap_or(JsonValue::Null)),
+        ("length", JsonValue::IntegerValue(info.map(|i| i.length as i64).unwrap_or(0))),
+    ]);
+}
+
+/// A document with its score and snippet for `query`, for grouped documents
+/// the files with hits, and with `explain` how the score came about.
+pub fn result(query: &str, corpus: &Corpus, id: DocId, score: f64, explain: bool) -> JsonValue {
+    let mut result = document(corpus, id);
+    if let JsonValue::ObjectValue(pairs) = &mut result {
+        let snippet = document_snippet(query, corpus, id, SNIPPET_WIDTH);
+        pairs.insert("score".to_owned(), JsonValue::DecimalValue(score));
+        pairs.insert("snippet".to_owned(), snippet.map(|(_, s)| s.to_json()).unwrap_or(JsonValue::Null));
+        if corpus.options.granularity != Granularity::File {
+            let files = file_hits(query, corpus, id).unwrap_or_default().iter()
+                .map(|(file, hits)| JsonValue::object(vec![
+                    ("path", path(file)),
+                    ("hits", JsonValue::IntegerValue(*hits as i64)),
+                ]))
+                .collect();
+            pairs.insert("files".to_owned(), JsonValue::ArrayValue(files));
+        }
+        if explain {
+            let explanation = search::explain(query, corpus, id).map(|e| e.to_json()).unwrap_or(JsonValue::Null);
+            pairs.insert("explanation".to_owned(), explanation);
+        }
+    }
+    return result;
+}
+
+/// Everything indexed for a document: its term counts and files too.
+pub fn document_detail(corpus: &Corpus, id: DocId) -> JsonValue {
+    let mut document = document(corpus, id);
+    if let JsonValue::ObjectValue(pairs) = &mut document {
+        let terms = corpus.index.get(&id).iter()
+            .flat_map(|doc| doc.iter())
+            .map(|(term, count)| (term.clone(), JsonValue::IntegerValue(*count as i64)))
+            .collect();
+        pairs.insert("terms".to_owned(), JsonValue::ObjectValue(terms));
+        let files = corpus.documents.get(id).map(|info| info.files.as_slice()).unwrap_or(&[]).iter()
+            .map(|file| JsonValue::object(vec![
+                ("path", path(&file.path)),
+                ("size", JsonValue::IntegerValue(file.size as i64)),
+                ("length", JsonValue::IntegerValue(file.length as i64)),
+            ]))
+            .collect();
+        pairs.insert("files".to_owned(), JsonValue::ArrayValue(files));
+    }
+    return document;
+}
+
+pub fn completions(corpus: &Corpus, prefix: &str, limit: usize, ranking: Ranking) -> JsonValue {
+    let words = corpus.triee.complete(&prefix.trim().to_lowercase(), limit, ranking).iter()
+        .map(|(word, score)| JsonValue::object(vec![
+            ("term", JsonValue::string(word)),
+            ("score", JsonValue::IntegerValue(*score as i64)),
+        ]))
+        .collect();
+    return JsonValue::ArrayValue(words);
+}
+
+/// Terms starting with `prefix` with their document and total frequencies,
+/// most common first.
+pub fn term_list(corpus: &Corpus, prefix: &str, limit: usize) -> Vec<(String, usize, usize)> {
+    let mut terms = corpus.triee.words_with_prefix(&prefix.to_lowercase())
+        .map(|word| {
+            let postings = corpus.triee.postings(&word).unwrap_or(&[]);
+            let total = postings.iter().map(|(_, count)| *count as usize).sum::<usize>();
+            (word, postings.len(), total)
+        })
+        .collect::<Vec<_>>();
+    terms.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)).then(a.0.cmp(&b.0)));
+    terms.truncate(limit);
+    return terms;
+}
+
+pub fn terms(terms: &[(String, usize, usize)]) -> JsonValue {
+    let terms = terms.iter()
+        .map(|(word, df, tf)| JsonValue::object(vec![
+            ("term", JsonValue::string(word)),
+            ("documents", JsonValue::IntegerValue(*df as i64)),
+            ("occurrences", JsonValue::IntegerValue(*tf as i64)),
+        ]))
+        .collect();
+    return JsonValue::ArrayValue(terms);
+}
+
+/// Corpus statistics as named values, in display order. Lists (top terms,
+/// largest documents) hold up to `limit` entries.
+pub fn stats(corpus: &Corpus, limit: usize) -> Vec<(&'static str, JsonValue)> {
+    let tokens: usize = corpus.documents.iter().map(|(_, d)| d.length).sum();
+    let bytes: u64 = corpus.documents.iter().map(|(_, d)| d.size).sum();
+    let files: usize = corpus.documents.iter().map(|(_, d)| d.files.len()).sum();
+    let mut fields = corpus.fields.keys().chain(corpus.numbers.fields()).cloned().collect::<Vec<_>>();
+    fields.sort();
+    fields.dedup();
+
+    let vocabulary = corpus.triee.iter()
+        .map(|entry| (entry.word.clone(), entry.document_frequency(), entry.total_frequency()))
+        .collect::<Vec<_>>();
+    let mut by_df = vocabulary.clone();
+    by_df.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)).then(a.0.cmp(&b.0)));
+    by_df.truncate(limit);
+    let mut by_tf = vocabulary;
+    by_tf.sort_by(|a, b| b.2.cmp(&a.2).then(b.1.cmp(&a.1)).then(a.0.cmp(&b.0)));
+    let mut single = by_tf.iter().filter(|(_, df, _)| *df == 1).cloned().collect::<Vec<_>>();
+    let single_count = single.len();
+    single.truncate(limit);
+    by_tf.truncate(limit);
+
+    let mut largest = corpus.documents.iter().collect::<Vec<_>>();
+    largest.sort_by(|a, b| b.1.length.cmp(&a.1.length).then(a.0.cmp(&b.0)));
+    let largest = largest.iter().take(limit)
+        .map(|(id, info)| JsonValue::object(vec![
+            ("path", path(&info.path)),
+            ("id", JsonValue::IntegerValue(*id as i64)),
+            ("length", JsonValue::IntegerValue(info.length as i64)),
+            ("bytes", JsonValue::IntegerValue(info.size as i64)),
+        ]))
+        .collect();
+
+    // extension -> (files, bytes, tokens)
+    let mut extensions: BTreeMap<String, (usize, u64, usize)> = BTreeMap::new();
+    for file in corpus.documents.iter().flat_map(|(_, d)| d.files.iter()) {
+        let extension = file.path.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default();
+        let totals = extensions.entry(extension).or_default();
+        totals.0 += 1;
+        totals.1 += file.size;
+        totals.2 += file.length;
+    }
+    let mut extensions = extensions.into_iter().collect::<Vec<_>>();
+    extensions.sort_by(|a, b| b.1.0.cmp(&a.1.0).then(a.0.cmp(&b.0)));
+    let extensions = extensions.iter()
+        .map(|(extension, (files, bytes, tokens))| JsonValue::object(vec![
+            ("extension", JsonValue::string(extension)),
+            ("files", JsonValue::IntegerValue(*files as i64)),
+            ("bytes", JsonValue::IntegerValue(*bytes as i64)),
+            ("tokens", JsonValue::IntegerValue(*tokens as i64)),
+        ]))
+        .collect();
+
+    let count = |n: usize| JsonValue::IntegerValue(n as i64);
+    let average = if corpus.documents.is_empty() { 0.0 } else { tokens as f64 / corpus.documents.len() as f64 };
+    return vec![
+        ("documents", count(corpus.documents.len())),
+        ("files", count(files)),
+        ("tokens", count(tokens)),
+        ("unique_terms", count(corpus.triee.len())),
+        ("average_length", JsonValue::DecimalValue(average)),
+        ("bytes", count(bytes as usize)),
+        ("trigrams", count(corpus.ngrams.as_ref().map(|n| n.len()).unwrap_or(0))),
+        ("skipped", count(corpus.skipped.len())),
+        ("fields", JsonValue::ArrayValue(fields.iter().map(|f| JsonValue::string(f)).collect())),
+        ("granularity", JsonValue::string(&corpus.options.granularity.to_string())),
+        ("single_document_terms", count(single_count)),
+        ("top_terms_by_df", terms(&by_df)),
+        ("top_terms_by_tf", terms(&by_tf)),
+        ("single_document_sample", terms(&single)),
+        ("largest_documents", JsonValue::ArrayValue(largest)),
+        ("extensions", JsonValue::ArrayValue(extensions)),
+    ];
+}
+
+/// A value for people rather than programs: strings unquoted, arrays as
+/// comma separated lists, objects as their term, path or extension followed
+/// by the other members.
+pub fn plain(value: &JsonValue) -> String {
+    return match value {
+        JsonValue::StringValue(s) => s.clone(),
+        JsonValue::DecimalValue(d) => format!("{:.4}", d),
+        JsonValue::ArrayValue(values) => values.iter().map(plain).collect::<Vec<_>>().join(", "),
+        JsonValue::ObjectValue(pairs) => {
+            let label = ["term", "path", "extension"].into_iter().find(|key| pairs.contains_key(*key));
+            let mut keys = pairs.keys().filter(|key| Some(key.as_str()) != label && *key != "id").collect::<Vec<_>>();
+            keys.sort();
+            let rest = keys.iter().map(|key| format!("{} {}", key, plain(&pairs[*key]))).collect::<Vec<_>>().join(", ");
+            match label {
+                Some(label) => format!("{:<32} {}", plain(&pairs[label]), rest),
+                None => rest,
+            }
+        }
+        value => value.to_string(),
+    };
+}
+
+/// Looks a document up by its path as indexed, or by the same file reached
+/// through another path.
+pub fn find_document(corpus: &Corpus, path: &Path) -> Option<DocId> {
+    if let Some(id) = corpus.documents.id_of(path) {
+        return Some(id);
+    }
+    let wanted = path.canonicalize().ok()?;
+    return corpus.documents.iter()
+        .find(|(_, info)| info.path.canonicalize().map(|p| p == wanted).unwrap_or(false))
+        .map(|(id, _)| id);
+}
+
+fn path(path: &Path) -> JsonValue {
+    return JsonValue::string(&path.display().to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{index_files::IndexOptions, testing::indexed};
+
+    fn stat<'a>(stats: &'a [(&str, JsonValue)], name: &str) -> &'a JsonValue {
+        return &stats.iter().find(|(key, _)| *key == name).unwrap().1;
+    }
+
+    fn term_names(value: &JsonValue) -> Vec<&str> {
+        return match value {
+            JsonValue::ArrayValue(terms) => terms.iter().map(|t| t.get("term").and_then(JsonValue::as_str).unwrap()).collect(),
+            _ => panic!("not a list of terms"),
+        };
+    }
+
+    #[test]
+    fn json_stats_count_words_not_punctuation() {
+        let (_tree, corpus) = indexed("stats", &[
+            ("a.json", br#"{"name": "alpha", "tags": ["beta", "alpha"]}"#),
+            ("b.json", br#"{"name": "beta"}"#),
+        ], IndexOptions::new(&["json"]));
+        let stats = stats(&corpus, 10);
+
+        assert_eq!(stat(&stats, "documents").as_i64(), Some(2));
+        assert_eq!(stat(&stats, "tokens").as_i64(), Some(7));
+        assert_eq!(stat(&stats, "unique_terms").as_i64(), Some(4));
+        assert_eq!(term_names(stat(&stats, "top_terms_by_df")), ["beta", "name", "alpha", "tags"]);
+        assert_eq!(term_names(stat(&stats, "top_terms_by_tf")), ["beta", "name", "alpha", "tags"]);
+        assert_eq!(term_names(stat(&stats, "single_document_sample")), ["alpha", "tags"]);
+    }
+}
@@ -0,0 +1,578 @@
+use std::{collections::{BTreeSet, HashMap}, path::{Path, PathBuf}};
+
+use crate::{
+    analyzer::Analyzers,
+    documents::DocId,
+    error::{Error, Result},
+    explain::{Explanation, Factor, PartExplanation},
+    extract,
+    index_files::{Corpus, Document, Index},
+    lexer::TokenKind,
+    ngram::NgramIndex,
+    numeric::{RangeQuery, DATE_FIELD, NUMBER_FIELD},
+    regex::{Regex, Requirement},
+    snippet::{best_snippet, Snippet},
+};
+
+enum QueryPart<'q> {
+    Terms(&'q str),
+    Field(&'q str, &'q str),
+    Wildcard(Wildcard),
+    Regex(Regex),
+    Range(RangeQuery),
+}
+
+enum Wildcard {
+    Prefix(String),
+    Suffix(String),
+    Infix(String),
+}
+
+impl Wildcard {
+    fn parse(piece: &str) -> Option<Self> {
+        let fragment = piece.trim_matches('*').to_lowercase();
+        if fragment.is_empty() || fragment.contains('*') {
+            return None;
+        }
+
+        return match (piece.starts_with('*'), piece.ends_with('*')) {
+            (true, true) => Some(Wildcard::Infix(fragment)),
+            (true, false) => Some(Wildcard::Suffix(fragment)),
+            (false, true) => Some(Wildcard::Prefix(fragment)),
+            (false, false) => None,
+        };
+    }
+
+    fn fragment(&self) -> &str {
+        return match self {
+            Wildcard::Prefix(f) | Wildcard::Suffix(f) | Wildcard::Infix(f) => f,
+        };
+    }
+
+    fn matches(&self, term: &str) -> bool {
+        let term = term.to_lowercase();
+        return match self {
+            Wildcard::Prefix(f) => term.starts_with(f.as_str()),
+            Wildcard::Suffix(f) => term.ends_with(f.as_str()),
+            Wildcard::Infix(f) => term.contains(f.as_str()),
+        };
+    }
+}
+
+/// Ranks documents for a query. Whitespace separated pieces wrapped in `*`
+/// (`*infix*`, `*suffix`, `prefix*`) match any indexed term containing the
+/// fragment, `/regex/` (or `/regex/i`) is matched against file contents,
+/// `field:[a TO b]`, `field:>v` and friends are numeric or date range
+/// queries, `title:term` and `heading:term` only look at those fields, and
+/// everything else is plain terms, split by the analyzers each document was
+/// indexed with.
+pub fn search_term(search_term: String, corpus: &Corpus) -> Result<Vec<(DocId, f64)>> {
+    let mut rankings: HashMap<DocId, f64> = HashMap::new();
+
+    for (_, part) in split_query(&search_term)? {
+        rank_part(&part, corpus, &mut rankings);
+    }
+
+    let mut res = rankings.into_iter().collect::<Vec<_>>();
+    res.sort_by_key(|(_, v)| (*v * 100000.0) as i64);
+
+    return Ok(res);
+}
+
+/// Takes the score of one document for a query apart, down to the tf and
+/// idf of every term, with the same formulas `search_term` uses.
+pub fn explain(query: &str, corpus: &Corpus, id: DocId) -> Result<Explanation> {
+    let mut parts = Vec::new();
+    for (source, part) in split_query(query)? {
+        parts.push(match part {
+            QueryPart::Terms(piece) => PartExplanation::new(source, "terms", term_factors(piece, &corpus.index, corpus, id)),
+            QueryPart::Field(field, piece) => {
+                let factors = corpus.fields.get(field)
+                    .map(|index| term_factors(piece, index, corpus, id))
+                    .unwrap_or_default();
+                PartExplanation::new(source, "field", factors)
+            }
+            QueryPart::Wildcard(wildcard) => {
+                let doc = corpus.index.get(&id);
+                let mut terms = doc.iter().flat_map(|doc| doc.iter())
+                    .filter(|(term, _)| wildcard.matches(term))
+                    .collect::<Vec<_>>();
+                terms.sort();
+                let factors = terms.into_iter()
+                    .map(|(term, count)| term_factor(term, *count, &corpus.index))
+                    .collect();
+                PartExplanation::new(source, "wildcard", factors)
+            }
+            QueryPart::Regex(regex) => {
+                let matches = regex_matches(&regex, corpus);
+                let factors = matches.iter()
+                    .find(|(doc, _)| *doc == id)
+                    .map(|(_, count)| {
+                        let documents = corpus.index.len();
+                        Factor::new(source, *count, tf_of(*count), matches.len(), documents, idf_of(matches.len(), documents))
+                    });
+                PartExplanation::new(source, "regex", factors.into_iter().collect())
+            }
+            QueryPart::Range(range) => {
+                let docs = corpus.numbers.range(&range);
+                let documents = corpus.index.len();
+                let factors = Some(Factor::new(source, 1, 1.0, docs.len(), documents, idf_of(docs.len(), documents)))
+                    .filter(|_| docs.contains(&id));
+                PartExplanation::new(source, "range", factors.into_iter().collect())
+            }
+        });
+    }
+    return Ok(Explanation::new(corpus.documents.path(id).to_path_buf(), parts));
+}
+
+fn term_factors(piece: &str, index: &Index, corpus: &Corpus, id: DocId) -> Vec<Factor> {
+    return document_terms(piece, corpus, id).into_iter()
+        .map(|term| {
+            let count = index.get(&id).and_then(|doc| doc.get(&term)).copied().unwrap_or(0);
+            term_factor(&term, count, index)
+        })
+        .collect();
+}
+
+/// Terms of a query piece as the analyzers of a document's files split
+/// them, so `example.com` or `don't` meet the terms they were indexed as.
+/// A grouped document of several kinds of files gets the terms of each.
+fn document_terms(piece: &str, corpus: &Corpus, id: DocId) -> Vec<String> {
+    let files = corpus.documents.get(id).map(|info| info.files.as_slice()).unwrap_or(&[]);
+    let mut terms: Vec<String> = Vec::new();
+    for analyzer in corpus.options.analyzers.for_paths(files.iter().map(|file| file.path.as_path())) {
+        // only what earlier analyzers didn't already give
+        let known = terms.len();
+        for term in analyzer.analyze(piece) {
+            if !terms[..known].iter().any(|t| *t == term.text) {
+                terms.push(term.text.into_owned());
+            }
+        }
+    }
+    return terms;
+}
+
+fn term_factor(term: &str, count: usize, index: &Index) -> Factor {
+    let df = document_frequency(term, index);
+    return Factor::new(term, count, tf_of(count), df, index.len(), idf_of(df, index.len()));
+}
+
+fn rank_part(part: &QueryPart, corpus: &Corpus, rankings: &mut HashMap<DocId, f64>) {
+    match part {
+        QueryPart::Terms(piece) => rank_terms(piece, &corpus.index, corpus, rankings),
+        QueryPart::Field(field, piece) => if let Some(index) = corpus.fields.get(*field) {
+            rank_terms(piece, index, corpus, rankings)
+        },
+        QueryPart::Wildcard(wildcard) => rank_wildcard(wildcard, corpus, rankings),
+        QueryPart::Regex(regex) => rank_regex(regex, corpus, rankings),
+        QueryPart::Range(range) => rank_range(range, corpus, rankings),
+    }
+}
+
+/// Byte ranges of `text`, the contents of `path`, the query matches, sorted
+/// and merged, for highlighting. Terms and wildcards match whole terms,
+/// ranges match numbers or dates inside them, and regexes their matches.
+pub fn query_hits(query: &str, text: &str, path: &Path, analyzers: &Analyzers) -> Result<Vec<(usize, usize)>> {
+    let parts = split_query(query)?.into_iter().map(|(_, part)| part).collect::<Vec<_>>();
+    let terms = parts.iter()
+        .filter_map(|part| match part {
+            QueryPart::Terms(piece) | QueryPart::Field(_, piece) => Some(analyzers.for_path(path).analyze(piece)),
+            _ => None,
+        })
+        .flatten()
+        .map(|t| t.text.into_owned())
+        .collect::<BTreeSet<_>>();
+
+    let mut hits = Vec::new();
+    for term in analyzers.for_path(path).analyze(text) {
+        let hit = terms.contains(&*term.text) || parts.iter().any(|part| match (part, term.kind) {
+            (QueryPart::Wildcard(wildcard), _) => wildcard.matches(&term.text),
+            (QueryPart::Range(range), TokenKind::Number(v)) => range.field != DATE_FIELD && range.contains(v),
+            (QueryPart::Range(range), TokenKind::Date(v)) => range.field != NUMBER_FIELD && range.contains(v),
+            _ => false,
+        });
+        if hit {
+            hits.push((term.start, term.end));
+        }
+    }
+
+    for part in &parts {
+        if let QueryPart::Regex(regex) = part {
+            hits.extend(regex.find_iter(text).filter(|(start, end)| start < end));
+        }
+    }
+
+    hits.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in hits {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    return Ok(merged);
+}
+
+/// The best snippet of a result for the query, from whichever of its files
+/// has the most hits.
+pub fn document_snippet(query: &str, corpus: &Corpus, id: DocId, width: usize) -> Option<(PathBuf, Snippet)> {
+    let mut best: Option<(usize, PathBuf, Snippet)> = None;
+    for (path, text) in corpus.read_document(id).ok()? {
+        let text = Corpus::visible_text(&path, text);
+        let hits = match query_hits(query, &text, &path, &corpus.options.analyzers) {
+            Ok(hits) => hits,
+            Err(_) => return None,
+        };
+        if best.as_ref().map(|(count, _, _)| hits.len() <= *count).unwrap_or(false) {
+            continue;
+        }
+        if let Some(snippet) = best_snippet(&text, &hits, width) {
+            best = Some((hits.len(), path, snippet));
+        }
+    }
+    return best.map(|(_, path, snippet)| (path, snippet));
+}
+
+/// How many query hits each file of a grouped document has, files without
+/// any left out, most hits first.
+pub fn file_hits(query: &str, corpus: &Corpus, id: DocId) -> Result<Vec<(PathBuf, usize)>> {
+    let mut files = Vec::new();
+    for (path, text) in corpus.read_document(id)? {
+        let text = Corpus::visible_text(&path, text);
+        let hits = query_hits(query, &text, &path, &corpus.options.analyzers)?.len();
+        if hits > 0 {
+            files.push((path, hits));
+        }
+    }
+    files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    return Ok(files);
+}
+
+/// The parts of a query, each with the text it was parsed from.
+fn split_query(query: &str) -> Result<Vec<(&str, QueryPart<'_>)>> {
+    let mut parts = Vec::new();
+    let mut rest = query.trim_start();
+
+    while !rest.is_empty() {
+        if let Some(body) = rest.strip_prefix('/') {
+            let close = find_closing_slash(body)
+                .ok_or_else(|| Error::Parse("Unterminated /regex/ in query".to_owned()))?;
+            let pattern = &body[..close];
+            let after = &body[close + 1..];
+            let flags_len = after.find(char::is_whitespace).unwrap_or(after.len());
+
+            let regex = match &after[..flags_len] {
+                "" => Regex::new(pattern)?,
+                "i" => Regex::with_case(pattern, true)?,
+                flags => return Err(Error::Parse(format!("Unknown regex flags: {}", flags))),
+            };
+            parts.push((&rest[..1 + close + 1 + flags_len], QueryPart::Regex(regex)));
+            rest = after[flags_len..].trim_start();
+            continue;
+        }
+
+        let end = range_length(rest)
+            .unwrap_or_else(|| rest.find(char::is_whitespace).unwrap_or(rest.len()));
+        let piece = &rest[..end];
+        let field = piece.split_once(':').filter(|(field, _)| extract::FIELDS.contains(field));
+        parts.push((piece, match (RangeQuery::parse(piece), Wildcard::parse(piece), field) {
+            (Some(range), _, _) => QueryPart::Range(range),
+            (None, Some(wildcard), _) => QueryPart::Wildcard(wildcard),
+            (None, None, Some((field, terms))) => QueryPart::Field(field, terms),
+            (None, None, None) => QueryPart::Terms(piece),
+        }));
+        rest = rest[end..].trim_start();
+    }
+
+    return Ok(parts);
+}
+
+/// Length of a bracketed `field:[a TO b]` piece, which contains spaces.
+fn range_length(rest: &str) -> Option<usize> {
+    let (field, spec) = rest.split_once(':')?;
+    if field.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let close = match spec.chars().next()? {
+        '[' => ']',
+        '{' => '}',
+        _ => return None,
+    };
+    return spec.find(close).map(|i| field.len() + 1 + i + 1);
+}
+
+fn find_closing_slash(body: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            '/' if !escaped => return Some(i),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    return None;
+}
+
+fn rank_terms(piece: &str, index: &Index, corpus: &Corpus, rankings: &mut HashMap<DocId, f64>) {
+    let mut idfs: HashMap<String, f64> = HashMap::new();
+    for (id, doc) in index {
+        let mut result = 0.0;
+        for term in document_terms(piece, corpus, *id) {
+            let tf = tf_in_document(&term, doc);
+            let idf = *idfs.entry(term).or_insert_with_key(|term| idf(term, index));
+            result += tf * idf;
+        }
+        if result <= 0.0 {
+            continue;
+        }
+
+        rankings.entry(*id)
+            .and_modify(|rank| *rank += result)
+            .or_insert(result);
+    }
+}
+
+/// Candidates come from the trigram index when it was built, and are then
+/// verified against the terms of each document.
+fn rank_wildcard(wildcard: &Wildcard, corpus: &Corpus, rankings: &mut HashMap<DocId, f64>) {
+    let candidates = corpus.ngrams.as_ref()
+        .and_then(|ngrams| ngrams.candidates(wildcard.fragment()));
+
+    let docs: Vec<(&DocId, &Document)> = match candidates {
+        Some(ids) => ids.iter()
+            .filter_map(|id| corpus.index.get_key_value(id))
+            .collect(),
+        None => corpus.index.iter().collect(),
+    };
+
+    let mut idfs: HashMap<&String, f64> = HashMap::new();
+    for (id, doc) in docs {
+        for term in doc.keys().filter(|t| wildcard.matches(t)) {
+            let idf = *idfs.entry(term).or_insert_with(|| idf(term, &corpus.index));
+            *rankings.entry(*id).or_insert(0.0) += tf_in_document(term, doc) * idf;
+        }
+    }
+}
+
+/// Scores by the number of matches.
+fn rank_regex(regex: &Regex, corpus: &Corpus, rankings: &mut HashMap<DocId, f64>) {
+    let matches = regex_matches(regex, corpus);
+    let idf = idf_of(matches.len(), corpus.index.len());
+    for (id, count) in matches {
+        *rankings.entry(id).or_insert(0.0) += tf_of(count) * idf;
+    }
+}
+
+/// Documents with matches and how many, in the decoded text the trigrams
+/// come from. The regex only runs on documents whose trigrams satisfy the
+/// pattern's required literals.
+fn regex_matches(regex: &Regex, corpus: &Corpus) -> Vec<(DocId, usize)> {
+    let candidates: Vec<DocId> = match corpus.ngrams.as_ref().and_then(|n| candidates_for(&regex.requirement(), n)) {
+        Some(ids) => ids.into_iter().collect(),
+        None => corpus.index.keys().copied().collect(),
+    };
+
+    let mut matches = Vec::new();
+    for id in candidates {
+        let count = corpus.read_document(id)
+            .map(|files| files.into_iter()
+                .map(|(path, text)| regex.find_iter(&corpus.decoded_text(&path, text)).count())
+                .sum())
+            .unwrap_or(0);
+        if count > 0 {
+            matches.push((id, count));
+        }
+    }
+    return matches;
+}
+
+fn rank_range(range: &RangeQuery, corpus: &Corpus, rankings: &mut HashMap<DocId, f64>) {
+    let docs = corpus.numbers.range(range);
+    let idf = idf_of(docs.len(), corpus.index.len());
+    for id in docs {
+        *rankings.entry(id).or_insert(0.0) += idf;
+    }
+}
+
+/// `None` stands for "every document".
+fn candidates_for(requirement: &Requirement, ngrams: &NgramIndex) -> Option<BTreeSet<DocId>> {
+    return match requirement {
+        Requirement::Anything => None,
+        Requirement::Literal(literal) => ngrams.candidates(literal),
+        Requirement::All(parts) => parts.iter()
+            .filter_map(|p| candidates_for(p, ngrams))
+            .reduce(|a, b| a.intersection(&b).copied().collect()),
+        Requirement::Any(parts) => {
+            let mut union = BTreeSet::new();
+            for part in parts {
+                union.extend(candidates_for(part, ngrams)?);
+            }
+            Some(union)
+        }
+    };
+}
+
+fn idf(term: &str, index: &Index) -> f64 {
+    return idf_of(document_frequency(term, index), index.len());
+}
+
+fn document_frequency(term: &str, index: &Index) -> usize {
+    return index.iter()
+        .filter(|(_, doc)| doc.contains_key(term))
+        .count();
+}
+
+fn idf_of(df: usize, documents: usize) -> f64 {
+    return ((documents as f64) / (df as f64 + 1.0)).ln() + 1.0;
+}
+
+fn tf_in_document(term: &String, document: &Document) -> f64 {
+    // not normalized by length, that would be `/ (document.len() as f64)`
+    return tf_of(document.get(term).copied().unwrap_or(0));
+}
+
+fn tf_of(count: usize) -> f64 {
+    return ((count as f64) + 1.0).ln();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{index_files::IndexOptions, testing::{indexed, TempTree}};
+
+    fn corpus(name: &str, files: &[(&str, &[u8])], ngrams: bool) -> (TempTree, Corpus) {
+        let mut options = IndexOptions::new(&["json", "html", "md"]);
+        options.ngrams = ngrams;
+        return indexed(name, files, options);
+    }
+
+    /// File names of the documents matching `query`.
+    fn hits(query: &str, corpus: &Corpus) -> Vec<String> {
+        let mut names = search_term(query.to_owned(), corpus).unwrap().into_iter()
+            .map(|(id, _)| corpus.documents.path(id).file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        return names;
+    }
+
+    const ESCAPED: &[(&str, &[u8])] = &[
+        ("escaped.json", br#"{"name": "caf\u00e9 cr\u00e8me"}"#),
+        ("entity.html", b"<p>caf&#233; <b>society</b></p>"),
+        ("plain.md", b"a cafeteria"),
+    ];
+
+    #[test]
+    fn wildcards_find_decoded_text() {
+        for ngrams in [true, false] {
+            let (_tree, corpus) = corpus("wildcard", ESCAPED, ngrams);
+            assert_eq!(hits("*afé*", &corpus), ["entity.html", "escaped.json"]);
+            assert_eq!(hits("caf*", &corpus), ["entity.html", "escaped.json", "plain.md"]);
+            assert_eq!(hits("*rème", &corpus), ["escaped.json"]);
+        }
+    }
+
+    #[test]
+    fn queries_split_like_each_document() {
+        let (_tree, corpus) = corpus("analyzers", &[
+            ("site.json", br#"{"url": "example.com", "note": "don't"}"#),
+            ("notes.md", b"Mail example.com, but don't."),
+            ("other.json", br#"{"text": "an example"}"#),
+        ], false);
+        assert_eq!(hits("example.com", &corpus), ["notes.md", "other.json", "site.json"]);
+        assert_eq!(hits("don't", &corpus), ["notes.md", "site.json"]);
+        assert_eq!(hits("EXAMPLE.COM", &corpus), ["notes.md", "other.json", "site.json"]);
+    }
+
+    #[test]
+    fn documents_without_the_terms_are_not_results() {
+        let (_tree, corpus) = corpus("no-match", ESCAPED, false);
+        assert!(hits("zzzznotthere", &corpus).is_empty());
+        assert_eq!(hits("society zzzznotthere", &corpus), ["entity.html"]);
+    }
+
+    #[test]
+    fn regexes_match_decoded_text() {
+        for ngrams in [true, false] {
+            let (_tree, corpus) = corpus("regex", ESCAPED, ngrams);
+            assert_eq!(hits("/afé/", &corpus), ["entity.html", "escaped.json"]);
+            assert_eq!(hits("/CAFÉ/i", &corpus), ["entity.html", "escaped.json"]);
+            assert_eq!(hits(r"/é\s+(cr|so)/", &corpus), ["entity.html", "escaped.json"]);
+            assert_eq!(hits("/caf.[ct]/", &corpus), ["plain.md"]);
+            // markup isn't text
+            assert!(hits("/<b>/", &corpus).is_empty());
+        }
+    }
+
+    #[test]
+    fn range_queries_find_values_after_their_key() {
+        let (_tree, corpus) = corpus("ranges", &[
+            ("cheap.json", br#"{"price": 9.5, "sold": "2024-01-02"}"#),
+            ("dear.json", br#"{"price": 120, "sold": "2024-03-01T10:00Z"}"#),
+            ("notes.md", b"Ten items at 15 each"),
+        ], false);
+        assert_eq!(hits("price:[0 TO 100]", &corpus), ["cheap.json"]);
+        assert_eq!(hits("price:>9.5", &corpus), ["dear.json"]);
+        assert_eq!(hits("number:[10 TO 20]", &corpus), ["notes.md"]);
+        assert_eq!(hits("sold:>=2024-02-01", &corpus), ["dear.json"]);
+        assert_eq!(hits("date:[2024-01-01 TO 2024-12-31]", &corpus), ["cheap.json", "dear.json"]);
+        assert!(hits("price:[100 TO 0]", &corpus).is_empty());
+    }
+
+    #[test]
+    fn text_without_escapes_keeps_its_backslashes() {
+        let (tree, corpus) = corpus("backslash", &[
+            ("path.md", br"Open C:\new\table, \x41 here"),
+            ("path.json", br#"{"path": "C:\\new", "letter": "\x41 here"}"#),
+        ], true);
+        let ngrams = corpus.ngrams.as_ref().unwrap();
+        let id = |name: &str| corpus.documents.id_of(&tree.root.join(name)).unwrap();
+        assert_eq!(ngrams.candidates(r"\ta"), Some([id("path.md")].into()));
+        assert_eq!(ngrams.candidates(r"\x4"), Some([id("path.md")].into()));
+        assert_eq!(ngrams.candidates("A h"), Some([id("path.json")].into()));
+        assert_eq!(hits("*able*", &corpus), ["path.md"]);
+    }
+
+    #[test]
+    fn regexes_match_backslashes_in_text_without_escapes() {
+        for ngrams in [true, false] {
+            let (_tree, corpus) = corpus("backslash-regex", &[
+                ("path.md", br"Open C:\new\table, \x41 here"),
+                ("path.json", br#"{"path": "C:\\new", "letter": "\x41 here"}"#),
+            ], ngrams);
+            assert_eq!(hits(r"/C:\\new\\table/", &corpus), ["path.md"]);
+            assert_eq!(hits(r"/\\x41 here/", &corpus), ["path.md"]);
+            assert_eq!(hits("/A here/", &corpus), ["path.json"]);
+            assert_eq!(hits(r"/C:\\new/", &corpus), ["path.json", "path.md"]);
+        }
+    }
+
+    #[test]
+    fn explained_scores_add_up_to_search_scores() {
+        let (_tree, corpus) = corpus("explain", &[
+            ("a.md", b"# Alpha\n\nalpha beta alphabet, 12 apples on 2024-03-01"),
+            ("b.md", b"beta beta gamma and 40 pears"),
+            ("c.json", br#"{"title": "alpha", "count": 7, "note": "gamma ray"}"#),
+        ], true);
+        let queries = [
+            "alpha", "beta gamma", "title:alpha", "alp*", "*et*", "/a[lp]+/", "/BETA/i",
+            "number:[10 TO 50]", "date:>2024-01-01", "alpha* /gamma/ number:<100",
+        ];
+        for query in queries {
+            let results = search_term(query.to_owned(), &corpus).unwrap();
+            assert!(!results.is_empty(), "{} found nothing", query);
+            for (id, score) in results {
+                let explanation = explain(query, &corpus, id).unwrap();
+                let factors = explanation.parts.iter()
+                    .flat_map(|part| part.factors.iter())
+                    .map(|factor| {
+                        assert!((factor.tf * factor.idf - factor.score).abs() < 1e-9, "{}: {:?}", query, factor);
+                        factor.score
+                    })
+                    .sum::<f64>();
+                assert!((explanation.score - score).abs() < 1e-9, "{}: {} != {}", query, explanation.score, score);
+                assert!((factors - score).abs() < 1e-9, "{}: {} != {}", query, factors, score);
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! Word boundaries following UAX #29 (Unicode Text Segmentation).
+//!
+//! The Word_Break property is derived from in-crate range tables for the
+//! classes that can't be told apart with std `char` methods; everything
+//! alphabetic that isn't listed falls back to ALetter. Scripts written
+//! without spaces (Han, Hiragana) get one segment per char, as in the
+//! standard, and are paired into bigrams by the `Lexer`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordBreak {
+    CR,
+    LF,
+    Newline,
+    Extend,
+    Zwj,
+    RegionalIndicator,
+    Format,
+    Katakana,
+    HebrewLetter,
+    ALetter,
+    SingleQuote,
+    DoubleQuote,
+    MidNumLet,
+    MidLetter,
+    MidNum,
+    Numeric,
+    ExtendNumLet,
+    WSegSpace,
+    ExtPict,
+    Other,
+}
+
+const EXTEND: &[(char, char)] = &[
+    ('\u{0300}', '\u{036F}'), ('\u{0483}', '\u{0489}'), ('\u{0591}', '\u{05BD}'),
+    ('\u{05BF}', '\u{05BF}'), ('\u{05C1}', '\u{05C2}'), ('\u{05C4}', '\u{05C5}'),
+    ('\u{05C7}', '\u{05C7}'), ('\u{0610}', '\u{061A}'), ('\u{064B}', '\u{065F}'),
+    ('\u{0670}', '\u{0670}'), ('\u{06D6}', '\u{06DC}'), ('\u{06DF}', '\u{06E4}'),
+    ('\u{06E7}', '\u{06E8}'), ('\u{06EA}', '\u{06ED}'), ('\u{0900}', '\u{0903}'),
+    ('\u{093A}', '\u{093C}'), ('\u{093E}', '\u{094F}'), ('\u{0951}', '\u{0957}'),
+    ('\u{0962}', '\u{0963}'), ('\u{0981}', '\u{0983}'), ('\u{09BC}', '\u{09BC}'),
+    ('\u{09BE}', '\u{09CD}'), ('\u{0E31}', '\u{0E31}'), ('\u{0E34}', '\u{0E3A}'),
+    ('\u{0E47}', '\u{0E4E}'), ('\u{1AB0}', '\u{1AFF}'), ('\u{1DC0}', '\u{1DFF}'),
+    ('\u{200C}', '\u{200C}'), ('\u{20D0}', '\u{20FF}'), ('\u{302A}', '\u{302F}'),
+    ('\u{3099}', '\u{309A}'), ('\u{FE00}', '\u{FE0F}'), ('\u{FE20}', '\u{FE2F}'),
+    ('\u{FF9E}', '\u{FF9F}'), ('\u{1F3FB}', '\u{1F3FF}'), ('\u{E0020}', '\u{E007F}'),
+    ('\u{E0100}', '\u{E01EF}'),
+];
+
+const FORMAT: &[(char, char)] = &[
+    ('\u{00AD}', '\u{00AD}'), ('\u{0600}', '\u{0605}'), ('\u{061C}', '\u{061C}'),
+    ('\u{06DD}', '\u{06DD}'), ('\u{070F}', '\u{070F}'), ('\u{200E}', '\u{200F}'),
+    ('\u{202A}', '\u{202E}'), ('\u{2060}', '\u{2064}'), ('\u{2066}', '\u{206F}'),
+    ('\u{FEFF}', '\u{FEFF}'), ('\u{FFF9}', '\u{FFFB}'),
+];
+
+const KATAKANA: &[(char, char)] = &[
+    ('\u{3031}', '\u{3035}'), ('\u{309B}', '\u{309C}'), ('\u{30A0}', '\u{30FA}'),
+    ('\u{30FC}', '\u{30FF}'), ('\u{31F0}', '\u{31FF}'), ('\u{32D0}', '\u{32FE}'),
+    ('\u{3300}', '\u{3357}'), ('\u{FF66}', '\u{FF9D}'),
+];
+
+const HEBREW_LETTER: &[(char, char)] = &[
+    ('\u{05D0}', '\u{05EA}'), ('\u{05EF}', '\u{05F2}'), ('\u{FB1D}', '\u{FB1D}'),
+    ('\u{FB1F}', '\u{FB28}'), ('\u{FB2A}', '\u{FB4F}'),
+];
+
+const MID_NUM_LET: &[char] = &['.', '\u{2018}', '\u{2019}', '\u{2024}', '\u{FE52}', '\u{FF07}', '\u{FF0E}'];
+
+const MID_LETTER: &[char] = &[
+    ':', '\u{00B7}', '\u{0387}', '\u{055F}', '\u{05F4}', '\u{2027}', '\u{FE13}', '\u{FE55}', '\u{FF1A}',
+];
+
+const MID_NUM: &[char] = &[
+    ',', ';', '\u{037E}', '\u{0589}', '\u{060C}', '\u{060D}', '\u{066C}', '\u{07F8}', '\u{2044}',
+    '\u{FE10}', '\u{FE14}', '\u{FE50}', '\u{FE54}', '\u{FF0C}', '\u{FF1B}',
+];
+
+const EXTEND_NUM_LET: &[(char, char)] = &[
+    ('_', '_'), ('\u{202F}', '\u{202F}'), ('\u{203F}', '\u{2040}'), ('\u{2054}', '\u{2054}'),
+    ('\u{FE33}', '\u{FE34}'), ('\u{FE4D}', '\u{FE4F}'), ('\u{FF3F}', '\u{FF3F}'),
+];
+
+const WSEG_SPACE: &[(char, char)] = &[
+    (' ', ' '), ('\u{1680}', '\u{1680}'), ('\u{2000}', '\u{2006}'), ('\u{2008}', '\u{200A}'),
+    ('\u{205F}', '\u{205F}'), ('\u{3000}', '\u{3000}'),
+];
+
+const EXT_PICT: &[(char, char)] = &[
+    ('\u{00A9}', '\u{00A9}'), ('\u{00AE}', '\u{00AE}'), ('\u{203C}', '\u{203C}'),
+    ('\u{2049}', '\u{2049}'), ('\u{2122}', '\u{2122}'), ('\u{2139}', '\u{2139}'),
+    ('\u{2194}', '\u{21AA}'), ('\u{231A}', '\u{23FF}'), ('\u{25AA}', '\u{25FE}'),
+    ('\u{2600}', '\u{27BF}'), ('\u{2B05}', '\u{2B55}'), ('\u{1F000}', '\u{1F0FF}'),
+    ('\u{1F10D}', '\u{1F1AD}'), ('\u{1F201}', '\u{1F251}'), ('\u{1F300}', '\u{1F3FA}'),
+    ('\u{1F400}', '\u{1FAFF}'),
+];
+
+/// Han ideographs and Hiragana: no Word_Break class of their own, so every
+/// char is a segment. Also the scripts the `Lexer` turns into bigrams.
+const CJK: &[(char, char)] = &[
+    ('\u{3005}', '\u{3007}'), ('\u{3021}', '\u{3029}'), ('\u{3038}', '\u{303C}'),
+    ('\u{3041}', '\u{3096}'), ('\u{309D}', '\u{309F}'), ('\u{3400}', '\u{4DBF}'),
+    ('\u{4E00}', '\u{9FFF}'), ('\u{F900}', '\u{FAFF}'), ('\u{20000}', '\u{2FA1F}'),
+    ('\u{30000}', '\u{3134F}'),
+];
+
+/// Southeast Asian scripts (Thai, Lao, Myanmar, Khmer) need dictionary based
+/// segmentation, which is out of scope; they are not ALetter.
+const SOUTH_EAST_ASIAN: &[(char, char)] = &[
+    ('\u{0E00}', '\u{0EFF}'), ('\u{1000}', '\u{109F}'), ('\u{1780}', '\u{17FF}'),
+    ('\u{19E0}', '\u{19FF}'), ('\u{AA60}', '\u{AA7F}'),
+];
+
+fn in_table(table: &[(char, char)], c: char) -> bool {
+    return table.binary_search_by(|(from, to)| {
+        if *to < c {
+            std::cmp::Ordering::Less
+        } else if *from > c {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    }).is_ok();
+}
+
+pub fn is_cjk(c: char) -> bool {
+    return in_table(CJK, c);
+}
+
+pub fn word_break(c: char) -> WordBreak {
+    return match c {
+        '\r' => WordBreak::CR,
+        '\n' => WordBreak::LF,
+        '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}' => WordBreak::Newline,
+        '\u{200D}' => WordBreak::Zwj,
+        '\'' => WordBreak::SingleQuote,
+        '"' => WordBreak::DoubleQuote,
+        '\u{1F1E6}'..='\u{1F1FF}' => WordBreak::RegionalIndicator,
+        c if MID_NUM_LET.contains(&c) => WordBreak::MidNumLet,
+        c if MID_LETTER.contains(&c) => WordBreak::MidLetter,
+        c if MID_NUM.contains(&c) => WordBreak::MidNum,
+        c if in_table(EXTEND, c) => WordBreak::Extend,
+        c if in_table(FORMAT, c) => WordBreak::Format,
+        c if in_table(KATAKANA, c) => WordBreak::Katakana,
+        c if in_table(HEBREW_LETTER, c) => WordBreak::HebrewLetter,
+        c if in_table(EXTEND_NUM_LET, c) => WordBreak::ExtendNumLet,
+        c if in_table(WSEG_SPACE, c) => WordBreak::WSegSpace,
+        c if in_table(EXT_PICT, c) => WordBreak::ExtPict,
+        c if c.is_numeric() && !('\u{FF10}'..='\u{FF19}').contains(&c) && !c.is_alphabetic() => WordBreak::Numeric,
+        c if c.is_alphabetic() && !is_cjk(c) && !in_table(SOUTH_EAST_ASIAN, c) => WordBreak::ALetter,
+        _ => WordBreak::Other,
+    };
+}
+
+fn is_ah_letter(wb: WordBreak) -> bool {
+    return matches!(wb, WordBreak::ALetter | WordBreak::HebrewLetter);
+}
+
+fn is_mid_num_let_q(wb: WordBreak) -> bool {
+    return matches!(wb, WordBreak::MidNumLet | WordBreak::SingleQuote);
+}
+
+fn is_ignorable(wb: WordBreak) -> bool {
+    return matches!(wb, WordBreak::Extend | WordBreak::Format | WordBreak::Zwj);
+}
+
+/// Skips Extend, Format and ZWJ chars starting at byte `i` (rule WB4).
+fn skip_ignorable(text: &str, i: usize) -> usize {
+    let skipped: usize = text[i..].chars()
+        .take_while(|c| is_ignorable(word_break(*c)))
+        .map(char::len_utf8)
+        .sum();
+    return i + skipped;
+}
+
+fn char_at(text: &str, i: usize) -> Option<char> {
+    return text[i..].chars().next();
+}
+
+/// Length in bytes of the word segment at the start of `text`. Chars are
+/// only decoded as far as the rules look ahead, so lexing a long run without
+/// whitespace stays linear.
+pub fn word_length(text: &str) -> usize {
+    let first_char = match text.chars().next() {
+        Some(c) => c,
+        None => return 0,
+    };
+    let first = word_break(first_char);
+
+    // WB3, WB3a
+    match first {
+        WordBreak::CR if text[1..].starts_with('\n') => return 2,
+        WordBreak::CR | WordBreak::LF | WordBreak::Newline => return first_char.len_utf8(),
+        _ => {}
+    }
+
+    let mut before_prev = WordBreak::Other;
+    let mut prev = first;
+    let mut regional_run = if first == WordBreak::RegionalIndicator { 1 } else { 0 };
+    let mut i = first_char.len_utf8();
+
+    loop {
+        // WB4
+        i = skip_ignorable(text, i);
+
+        let c = match char_at(text, i) {
+            Some(c) => c,
+            None => return i,
+        };
+        let current = word_break(c);
+        let after = skip_ignorable(text, i + c.len_utf8());
+        let next = char_at(text, after).map(word_break).unwrap_or(WordBreak::Other);
+
+        let joins = match (prev, current) {
+            (_, WordBreak::CR | WordBreak::LF | WordBreak::Newline) => false,
+            // WB3c
+            (_, WordBreak::ExtPict) if text[..i].ends_with('\u{200D}') => true,
+            (WordBreak::WSegSpace, WordBreak::WSegSpace) => true,
+            (p, c) if is_ah_letter(p) && is_ah_letter(c) => true,
+            (p, c) if is_ah_letter(p) && (c == WordBreak::MidLetter || is_mid_num_let_q(c)) => is_ah_letter(next),
+            (p, c) if (p == WordBreak::MidLetter || is_mid_num_let_q(p)) && is_ah_letter(c) => is_ah_letter(before_prev),
+            (WordBreak::HebrewLetter, WordBreak::SingleQuote) => true,
+            (WordBreak::HebrewLetter, WordBreak::DoubleQuote) => next == WordBreak::HebrewLetter,
+            (WordBreak::DoubleQuote, WordBreak::HebrewLetter) => before_prev == WordBreak::HebrewLetter,
+            (WordBreak::Numeric, WordBreak::Numeric) => true,
+            (p, WordBreak::Numeric) if is_ah_letter(p) => true,
+            (WordBreak::Numeric, c) if is_ah_letter(c) => true,
+            (WordBreak::Numeric, c) if c == WordBreak::MidNum || is_mid_num_let_q(c) => next == WordBreak::Numeric,
+            (p, WordBreak::Numeric) if p == WordBreak::MidNum || is_mid_num_let_q(p) => before_prev == WordBreak::Numeric,
+            (WordBreak::Katakana, WordBreak::Katakana) => true,
+            (p, WordBreak::ExtendNumLet) if is_ah_letter(p)
+                || matches!(p, WordBreak::Numeric | WordBreak::Katakana | WordBreak::ExtendNumLet) => true,
+            (WordBreak::ExtendNumLet, c) if is_ah_letter(c)
+                || matches!(c, WordBreak::Numeric | WordBreak::Katakana) => true,
+            (WordBreak::RegionalIndicator, WordBreak::RegionalIndicator) => regional_run % 2 == 1,
+            _ => false,
+        };
+
+        if !joins {
+            return i;
+        }
+
+        regional_run = if current == WordBreak::RegionalIndicator { regional_run + 1 } else { 0 };
+        before_prev = prev;
+        prev = current;
+        i += c.len_utf8();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, LexerMode};
+
+    /// The segments of `text`, whitespace included.
+    fn segments(text: &str) -> Vec<&str> {
+        let mut segments = Vec::new();
+        let mut rest = text;
+        while !rest.is_empty() {
+            let n = word_length(rest).max(rest.chars().next().unwrap().len_utf8());
+            segments.push(&rest[..n]);
+            rest = &rest[n..];
+        }
+        return segments;
+    }
+
+    fn tokens(text: &str) -> Vec<&str> {
+        return Lexer::with_mode(text, LexerMode::Unicode).collect();
+    }
+
+    #[test]
+    fn apostrophes_join_letters() {
+        assert_eq!(segments("don't stop"), ["don't", " ", "stop"]);
+        assert_eq!(segments("o\u{2019}clock"), ["o\u{2019}clock"]);
+        assert_eq!(segments("dogs' bowls"), ["dogs", "'", " ", "bowls"]);
+        assert_eq!(segments("'quoted'"), ["'", "quoted", "'"]);
+        assert_eq!(segments("e.g. x"), ["e.g", ".", " ", "x"]);
+        assert_eq!(segments("\u{05D0}'\u{05D1}"), ["\u{05D0}'\u{05D1}"]);
+    }
+
+    #[test]
+    fn mid_number_punctuation() {
+        assert_eq!(segments("3.14"), ["3.14"]);
+        assert_eq!(segments("1,000,000"), ["1,000,000"]);
+        assert_eq!(segments("1,000, then"), ["1,000", ",", " ", "then"]);
+        assert_eq!(segments("3."), ["3", "."]);
+        assert_eq!(segments("v2x_9"), ["v2x_9"]);
+        // `:` is MidLetter, it joins letters but not digits
+        assert_eq!(segments("a:b 1:2"), ["a:b", " ", "1", ":", "2"]);
+    }
+
+    #[test]
+    fn mixed_latin_and_cjk() {
+        assert_eq!(segments("abc漢字def"), ["abc", "漢", "字", "def"]);
+        assert_eq!(segments("カタカナ漢"), ["カタカナ", "漢"]);
+        assert_eq!(tokens("Rust東京都ok"), ["Rust", "東京", "京都", "ok"]);
+        assert_eq!(tokens("日本 語"), ["日本", "語"]);
+        assert_eq!(tokens("ひらがな"), ["ひら", "らが", "がな"]);
+    }
+
+    #[test]
+    fn emoji_sequences() {
+        // family: man ZWJ woman ZWJ girl
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(segments(family), [family]);
+        // skin tone modifiers are Extend
+        assert_eq!(segments("\u{1F44D}\u{1F3FD}!"), ["\u{1F44D}\u{1F3FD}", "!"]);
+        // flags pair regional indicators two by two
+        let flags = "\u{1F1E9}\u{1F1EA}\u{1F1EB}\u{1F1F7}\u{1F1EE}";
+        assert_eq!(segments(flags), ["\u{1F1E9}\u{1F1EA}", "\u{1F1EB}\u{1F1F7}", "\u{1F1EE}"]);
+        // a ZWJ inside a word doesn't split it
+        assert_eq!(segments("ab\u{200D}cd x"), ["ab\u{200D}cd", " ", "x"]);
+    }
+
+    #[test]
+    fn whitespace_and_newlines() {
+        assert_eq!(segments("a  b"), ["a", "  ", "b"]);
+        assert_eq!(segments("a\r\n\nb"), ["a", "\r\n", "\n", "b"]);
+        assert_eq!(segments("caf\u{0065}\u{0301} ok"), ["caf\u{0065}\u{0301}", " ", "ok"]);
+    }
+
+    #[test]
+    fn long_runs_without_whitespace() {
+        let run = "a,".repeat(20_000);
+        assert_eq!(tokens(&run).len(), 40_000);
+        let number = "1,2".repeat(20_000);
+        assert_eq!(segments(&number), [number.as_str()]);
+    }
+}
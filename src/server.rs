@@ -0,0 +1,254 @@
+//! A small HTTP/1.1 server answering JSON over a loaded corpus. Requests are
+//! handled one at a time, each connection is closed after its response.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    error::{Error, Result},
+    index_files::Corpus,
+    log::info,
+    parse::JsonValue,
+    report,
+    search::search_term,
+    trie::Ranking,
+};
+
+/// Longest request line or header line accepted.
+const MAX_LINE: usize = 8192;
+const MAX_HEADERS: usize = 100;
+const DEFAULT_LIMIT: usize = 15;
+
+pub struct Response {
+    pub status: u16,
+    pub body: JsonValue,
+}
+
+impl Response {
+    fn ok(body: JsonValue) -> Self {
+        return Self { status: 200, body };
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        return Self { status, body: JsonValue::object(vec![("error", JsonValue::string(message))]) };
+    }
+}
+
+/// Serves until the listener fails. A broken connection only ends that
+/// connection.
+pub fn serve(corpus: &Corpus, address: &str) -> Result<()> {
+    let listener = TcpListener::bind(address).map_err(|e| Error::io(Path::new(address), e))?;
+    let local = listener.local_addr().map_err(|e| Error::io(Path::new(address), e))?;
+    info!("Listening on http://{}", local);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("error: {}", Error::io(Path::new(address), e));
+                continue;
+            }
+        };
+        if let Err(e) = handle_connection(corpus, stream) {
+            eprintln!("error: {}", e);
+        }
+    }
+    return Ok(());
+}
+
+fn handle_connection(corpus: &Corpus, stream: TcpStream) -> Result<()> {
+    let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_else(|_| "<client>".to_owned());
+    let io_error = |e| Error::io(Path::new(&peer), e);
+    stream.set_read_timeout(Some(Duration::from_secs(10))).map_err(io_error)?;
+
+    let mut reader = BufReader::new(&stream);
+    let response = match read_request(&mut reader).map_err(io_error)? {
+        Some((method, target)) => respond(corpus, &method, &target),
+        None => Response::error(400, "Malformed request"),
+    };
+
+    let body = response.body.to_string();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n",
+        response.status,
+        reason(response.status),
+        body.len(),
+        if response.status == 405 { "Allow: GET\r\n" } else { "" },
+    );
+    let mut stream = &stream;
+    stream.write_all(head.as_bytes()).map_err(io_error)?;
+    stream.write_all(body.as_bytes()).map_err(io_error)?;
+    return stream.flush().map_err(io_error);
+}
+
+/// Method and target of the request, its headers read and dropped. `None`
+/// for anything that isn't HTTP.
+fn read_request(reader: &mut impl BufRead) -> std::io::Result<Option<(String, String)>> {
+    let request_line = match read_line(reader)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    for _ in 0..MAX_HEADERS {
+        match read_line(reader)? {
+            Some(line) if line.is_empty() => break,
+            Some(_) => {}
+            None => return Ok(None),
+        }
+    }
+
+    let mut words = request_line.split(' ');
+    return Ok(match (words.next(), words.next(), words.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+            Some((method.to_owned(), target.to_owned()))
+        }
+        _ => None,
+    });
+}
+
+fn read_line(reader: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let mut line = Vec::new();
+    let read = reader.by_ref().take(MAX_LINE as u64).read_until(b'\n', &mut line)?;
+    if read == 0 || !line.ends_with(b"\n") {
+        return Ok(None);
+    }
+    let line = String::from_utf8_lossy(&line);
+    return Ok(Some(line.trim_end_matches(['\r', '\n']).to_owned()));
+}
+
+/// Routes one request.
+pub fn respond(corpus: &Corpus, method: &str, target: &str) -> Response {
+    if method != "GET" {
+        return Response::error(405, "Only GET is supported");
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params = parse_params(query);
+    let param = |name: &str| params.get(name).map(String::as_str);
+    let limit = match param("limit").map(str::parse::<usize>) {
+        None => DEFAULT_LIMIT,
+        Some(Ok(limit)) => limit,
+        Some(Err(_)) => return Response::error(400, "limit must be a number"),
+    };
+
+    return match path {
+        "/search" => {
+            let query = match param("q") {
+                Some(q) if !q.trim().is_empty() => q,
+                _ => return Response::error(400, "Missing q"),
+            };
+            let offset = param("offset").and_then(|o| o.parse::<usize>().ok()).unwrap_or(0);
+            let explain = matches!(param("explain"), Some("" | "1" | "true"));
+            match search_term(query.to_owned(), corpus) {
+                Ok(results) => {
+                    let page = results.iter().rev().skip(offset).take(limit)
+                        .map(|(id, score)| report::result(query, corpus, *id, *score, explain))
+                        .collect();
+                    Response::ok(JsonValue::object(vec![
+                        ("query", JsonValue::string(query)),
+                        ("total", JsonValue::IntegerValue(results.len() as i64)),
+                        ("offset", JsonValue::IntegerValue(offset as i64)),
+                        ("results", JsonValue::ArrayValue(page)),
+                    ]))
+                }
+                Err(e) => Response::error(400, &e.to_string()),
+            }
+        }
+        "/complete" => {
+            let prefix = param("prefix").unwrap_or("");
+            let ranking = match param("ranking") {
+                None | Some("df") => Ranking::DocumentFrequency,
+                Some("tf") => Ranking::TotalFrequency,
+                Some(_) => return Response::error(400, "ranking must be df or tf"),
+            };
+            Response::ok(JsonValue::object(vec![
+                ("prefix", JsonValue::string(prefix)),
+                ("completions", report::completions(corpus, prefix, limit, ranking)),
+            ]))
+        }
+        "/doc" => match param("path").map(|p| report::find_document(corpus, Path::new(p))) {
+            None => Response::error(400, "Missing path"),
+            Some(None) => Response::error(404, "No such document"),
+            Some(Some(id)) => Response::ok(report::document_detail(corpus, id)),
+        },
+        "/stats" => Response::ok(JsonValue::object(report::stats(corpus, limit))),
+        _ => Response::error(404, "Unknown endpoint, try /search, /complete, /doc or /stats"),
+    };
+}
+
+/// `a=1&b=x+y` with `%XX` escapes. The first of repeated names wins.
+fn parse_params(query: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let decode = |text: &str| percent_decode(&text.replace('+', " "));
+        params.entry(decode(name)).or_insert_with(|| decode(value));
+    }
+    return params;
+}
+
+/// Undoes `%XX` escapes, leaving malformed ones as they are.
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    return String::from_utf8_lossy(&decoded).into_owned();
+}
+
+fn reason(status: u16) -> &'static str {
+    return match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{index_files::IndexOptions, testing::indexed};
+
+    #[test]
+    fn search_totals_count_only_matches() {
+        let (_tree, corpus) = indexed("server", &[
+            ("a.md", b"alpha beta"),
+            ("b.md", b"beta gamma"),
+            ("c.md", b"gamma delta"),
+        ], IndexOptions::new(&["md"]));
+        let total = |body: &JsonValue| body.get("total").and_then(JsonValue::as_i64);
+        let results = |body: &JsonValue| match body.get("results") {
+            Some(JsonValue::ArrayValue(results)) => results.len(),
+            _ => panic!("no results in {}", body),
+        };
+
+        let response = respond(&corpus, "GET", "/search?q=zzzznotthere");
+        assert_eq!(response.status, 200);
+        assert_eq!(total(&response.body), Some(0));
+        assert_eq!(results(&response.body), 0);
+
+        let response = respond(&corpus, "GET", "/search?q=beta&limit=1");
+        assert_eq!(total(&response.body), Some(2));
+        assert_eq!(results(&response.body), 1);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{extract, parse::JsonValue};
+
+/// Default snippet width in bytes.
+pub const SNIPPET_WIDTH: usize = 160;
+
+/// How far a snippet edge may move to land on whitespace instead of
+/// cutting a word.
+const EDGE_SLACK: usize = 20;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    /// Bold red through ANSI escapes, for terminals.
+    Ansi,
+    /// HTML escaped, hits wrapped in `<mark>`.
+    Html,
+    /// Hits wrapped in `[` `]`.
+    Plain,
+}
+
+/// A window of a document with the query hits inside it. Offsets in `hits`
+/// are relative to `text`; `start` is where `text` begins in the document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snippet {
+    pub start: usize,
+    pub text: String,
+    pub hits: Vec<(usize, usize)>,
+}
+
+/// Picks the `width` byte window of `text` holding the most hits, preferring
+/// the earliest one on ties. `hits` must be sorted and not overlap.
+pub fn best_snippet(text: &str, hits: &[(usize, usize)], width: usize) -> Option<Snippet> {
+    if hits.is_empty() {
+        return None;
+    }
+
+    let (mut best, mut best_count) = (0, 0);
+    let mut last = 0;
+    for first in 0..hits.len() {
+        last = last.max(first);
+        while last + 1 < hits.len() && hits[last + 1].1 - hits[first].0 <= width {
+            last += 1;
+        }
+        if last - first + 1 > best_count {
+            best = first;
+            best_count = last - first + 1;
+        }
+    }
+
+    // center the hits in the window
+    let covered = hits[best + best_count - 1].1 - hits[best].0;
+    let start = hits[best].0.saturating_sub(width.saturating_sub(covered) / 2);
+    let end = (start + width).max(hits[best + best_count - 1].1).min(text.len());
+    let start = end.saturating_sub(width).min(start);
+
+    let start = snap(text, start, false).min(hits[best].0);
+    let end = snap(text, end, true).max(hits[best + best_count - 1].1);
+
+    let hits = hits.iter()
+        .filter(|(s, e)| *s >= start && *e <= end)
+        .map(|(s, e)| (s - start, e - start))
+        .collect();
+    return Some(Snippet { start, text: text[start..end].to_string(), hits });
+}
+
+/// Moves `at` to a char boundary, then to nearby whitespace if there is any
+/// within `EDGE_SLACK` bytes, going outwards from the window.
+fn snap(text: &str, mut at: usize, forward: bool) -> usize {
+    while !text.is_char_boundary(at) {
+        if forward { at += 1 } else { at -= 1 }
+    }
+
+    let space = if forward {
+        text[at..].char_indices()
+            .take_while(|(i, _)| *i <= EDGE_SLACK)
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, _)| at + i)
+    } else {
+        text[..at].char_indices().rev()
+            .take_while(|(i, _)| at - i <= EDGE_SLACK)
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, c)| i + c.len_utf8())
+    };
+
+    return match space {
+        Some(space) => space,
+        None if forward => text.len().min(at),
+        None => at,
+    };
+}
+
+#[allow(dead_code)]
+impl Snippet {
+
+    /// One line of text with the hits highlighted. Runs of whitespace become
+    /// a single space so the snippet fits on a single line.
+    pub fn render(&self, highlight: Highlight) -> String {
+        let (open, close) = match highlight {
+            Highlight::Ansi => ("\x1b[1;31m", "\x1b[0m"),
+            Highlight::Html => ("<mark>", "</mark>"),
+            Highlight::Plain => ("[", "]"),
+        };
+
+        let mut output = String::with_capacity(self.text.len());
+        let mut at = 0;
+        for &(start, end) in &self.hits {
+            push_text(&mut output, &self.text[at..start], highlight);
+            output.push_str(open);
+            push_text(&mut output, &self.text[start..end], highlight);
+            output.push_str(close);
+            at = end;
+        }
+        push_text(&mut output, &self.text[at..], highlight);
+
+        return output.trim().to_owned();
+    }
+
+    /// `{"start": .., "text": "..", "hits": [[start, end], ..]}` with the
+    /// offsets of `hits` relative to `text`.
+    pub fn to_json(&self) -> JsonValue {
+        let hits = self.hits.iter()
+            .map(|(s, e)| JsonValue::ArrayValue(vec![
+                JsonValue::IntegerValue(*s as i64),
+                JsonValue::IntegerValue(*e as i64),
+            ]))
+            .collect();
+        return JsonValue::object(vec![
+            ("start", JsonValue::IntegerValue(self.start as i64)),
+            ("text", JsonValue::string(&self.text)),
+            ("hits", JsonValue::ArrayValue(hits)),
+        ]);
+    }
+}
+
+fn push_text(output: &mut String, text: &str, highlight: Highlight) {
+    for c in text.chars() {
+        match c {
+            c if c.is_whitespace() => {
+                if !output.ends_with(' ') {
+                    output.push(' ');
+                }
+            }
+            '<' if highlight == Highlight::Html => output.push_str("&lt;"),
+            '>' if highlight == Highlight::Html => output.push_str("&gt;"),
+            '&' if highlight == Highlight::Html => output.push_str("&amp;"),
+            '"' if highlight == Highlight::Html => output.push_str("&quot;"),
+            c if c.is_control() || extract::is_padding(c) => {}
+            c => output.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Spans of every occurrence of `word` in `text`.
+    fn hits_of(text: &str, word: &str) -> Vec<(usize, usize)> {
+        return text.match_indices(word).map(|(i, w)| (i, i + w.len())).collect();
+    }
+
+    #[test]
+    fn window_holds_the_most_hits() {
+        let text = format!("cat {} cat dog cat {}", "x ".repeat(100), "y ".repeat(100));
+        let hits = hits_of(&text, "cat");
+        let snippet = best_snippet(&text, &hits, 40).unwrap();
+
+        assert_eq!(snippet.hits.len(), 2);
+        assert!(snippet.text.contains("cat dog cat"));
+        assert_eq!(&text[snippet.start..snippet.start + snippet.text.len()], snippet.text);
+    }
+
+    #[test]
+    fn ties_go_to_the_earliest_window() {
+        let text = format!("one {} two {} three", "x ".repeat(100), "y ".repeat(100));
+        let hits = [hits_of(&text, "one"), hits_of(&text, "two"), hits_of(&text, "three")].concat();
+        let snippet = best_snippet(&text, &hits, 30).unwrap();
+
+        assert_eq!(snippet.start, 0);
+        assert_eq!(snippet.hits, [(0, 3)]);
+        assert!(best_snippet(&text, &[], 30).is_none());
+    }
+
+    #[test]
+    fn edges_snap_to_whitespace() {
+        let text = "alpha beta gamma delta epsilon zeta eta theta iota kappa";
+        let hits = hits_of(text, "epsilon");
+        let snippet = best_snippet(text, &hits, 20).unwrap();
+
+        assert_eq!(snippet.text, "delta epsilon zeta eta");
+        assert!(text[..snippet.start].ends_with(' '));
+        assert!(text[snippet.start + snippet.text.len()..].starts_with(' '));
+        let (s, e) = snippet.hits[0];
+        assert_eq!(&snippet.text[s..e], "epsilon");
+    }
+
+    #[test]
+    fn edges_land_on_char_boundaries() {
+        // no whitespace to snap to, and every char takes two or more bytes
+        let text = format!("{}日本語{}", "é".repeat(200), "ü".repeat(200));
+        let hits = hits_of(&text, "本");
+        for width in [10, 11, 12, 13, 51] {
+            let snippet = best_snippet(&text, &hits, width).unwrap();
+            assert!(text.is_char_boundary(snippet.start));
+            let (s, e) = snippet.hits[0];
+            assert_eq!(&snippet.text[s..e], "本");
+            assert_eq!(snippet.start + s, hits[0].0);
+        }
+    }
+
+    #[test]
+    fn render_highlights_hits() {
+        let snippet = Snippet { start: 4, text: " a <b>\n\n  & \"c\"\u{ad} ".to_owned(), hits: vec![(3, 6), (13, 14)] };
+
+        assert_eq!(snippet.render(Highlight::Plain), "a [<b>] & \"[c]\"");
+        assert_eq!(snippet.render(Highlight::Html), "a <mark>&lt;b&gt;</mark> &amp; &quot;<mark>c</mark>&quot;");
+        assert_eq!(snippet.render(Highlight::Ansi), "a \x1b[1;31m<b>\x1b[0m & \"\x1b[1;31mc\x1b[0m\"");
+    }
+}
@@ -0,0 +1,43 @@
+//! Fixtures shared by the unit tests.
+
+use std::path::PathBuf;
+
+use crate::{
+    index_files::{index, Corpus, IndexOptions},
+    log::{self, Level},
+};
+
+/// A fresh folder under the system temp dir, removed again when dropped.
+pub struct TempTree {
+    pub root: PathBuf,
+}
+
+impl TempTree {
+    /// Holds `files`, given as paths relative to the root and their
+    /// contents. Logging is turned off, tests check what gets returned.
+    pub fn new(name: &str, files: &[(&str, &[u8])]) -> Self {
+        log::set_level(Level::Quiet);
+        let root = std::env::temp_dir().join(format!("fji-tf-idf-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        return Self { root };
+    }
+}
+
+impl Drop for TempTree {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// `files` indexed with `options`. The tree has to outlive the corpus where
+/// files are read again, as regexes and snippets do.
+pub fn indexed(name: &str, files: &[(&str, &[u8])], options: IndexOptions) -> (TempTree, Corpus) {
+    let tree = TempTree::new(name, files);
+    let corpus = index(&tree.root, options).unwrap();
+    return (tree, corpus);
+}
@@ -1,116 +1,626 @@
-use std::{path::PathBuf, collections::{HashMap, HashSet}};
+use std::{path::Path, collections::BTreeSet};
 
+use crate::{documents::{DocId, DocumentRegistry}, binary::{self, Reader}, error::Error};
 
+const TRIEE_MAGIC: &[u8] = b"TRIE\x01";
+/// Deepest node nesting decoding accepts. Every level adds at least a byte
+/// to the words below it, so only corrupt data gets anywhere near.
+const MAX_DEPTH: usize = 256;
+
+/// Radix (Patricia) prefix tree. Chains of single-child nodes are collapsed
+/// into edge labels, and documents are referenced by their `DocId` in the
+/// `DocumentRegistry`.
 #[allow(dead_code)]
+#[derive(Default)]
 pub struct Triee {
     root: TrieeNode,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ranking {
+    DocumentFrequency,
+    TotalFrequency,
+}
+
 #[allow(dead_code)]
 impl <'a> Triee {
 
     pub fn new() -> Self {
-        return Self { root: TrieeNode::new() };
+        return Self::default();
+    }
+
+    pub fn docs_for_prefix(&self, word: &str) -> Option<BTreeSet<DocId>> {
+        let (node, _) = Self::node_for_prefix(&self.root, word)?;
+        let mut docs = BTreeSet::new();
+        node.collect_docs(&mut docs);
+        return Some(docs);
+    }
+
+    pub fn paths_for_prefix<'r>(&self, word: &str, documents: &'r DocumentRegistry) -> Option<Vec<&'r Path>> {
+        return self.docs_for_prefix(word)
+            .map(|docs| docs.into_iter().map(|d| documents.path(d)).collect());
+    }
+
+    /// Number of distinct words stored.
+    pub fn len(&self) -> usize {
+        return self.root.count_ends();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.root.children.is_empty() && !self.root.is_end();
+    }
+
+    /// Per-document occurrence counts of an exact word.
+    pub fn postings(&self, word: &str) -> Option<&[(DocId, u32)]> {
+        let (node, walked) = Self::node_for_prefix(&self.root, word)?;
+        if walked.len() != word.len() || !node.is_end() {
+            return None;
+        }
+        return Some(&node.postings);
+    }
+
+    /// Every stored word with its postings, in lexicographic order.
+    pub fn iter(&self) -> Iter<'_> {
+        return Iter::new(&self.root, String::new());
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = String> + '_ {
+        return self.iter().map(|e| e.word);
+    }
+
+    pub fn words_with_prefix(&self, prefix: &str) -> impl Iterator<Item = String> + '_ {
+        let iter = match Self::node_for_prefix(&self.root, prefix) {
+            Some((node, walked)) => Iter::new(node, walked),
+            None => Iter::empty(),
+        };
+        return iter.map(|e| e.word);
     }
 
-    pub fn paths_for_prefix(&self, word: &[char]) -> Option<&HashSet<PathBuf>> {
-        return Self::paths_for_prefix_static(&self.root, word);
+    pub fn iter_with_paths<'r>(&'a self, documents: &'r DocumentRegistry)
+        -> impl Iterator<Item = (String, Vec<(&'r Path, u32)>)> + 'a
+    where 'r: 'a {
+        return self.iter().map(move |e| {
+            let paths = e.postings.iter()
+                .map(|(doc, count)| (documents.path(*doc), *count))
+                .collect();
+            (e.word, paths)
+        });
     }
 
-    pub fn insert_word(&mut self, word: &[char], found_at: PathBuf) {
+    pub fn insert_word(&mut self, word: &str, found_at: DocId) {
         Self::insert_static(&mut self.root, word, found_at);
     }
 
-    pub fn print_ends(&self) {
-        Self::ptrint_end_nodes(&self.root, Vec::new());
+    /// Removes every occurrence of a document, e.g. after its file was deleted.
+    /// Returns whether the document was present at all.
+    pub fn remove_path(&mut self, doc: DocId) -> bool {
+        return Self::remove_doc_static(&mut self.root, doc);
+    }
+
+    /// Removes a word together with all of its postings.
+    /// Returns whether the word was present.
+    pub fn remove_word(&mut self, word: &str) -> bool {
+        return Self::remove_word_static(&mut self.root, word);
+    }
+
+    /// Returns up to `k` words starting with `prefix`, best ranked first.
+    /// Ties are broken alphabetically so the output is stable.
+    pub fn complete(&self, prefix: &str, k: usize, ranking: Ranking) -> Vec<(String, usize)> {
+        let (start, mut walked) = match Self::node_for_prefix(&self.root, prefix) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+
+        let mut found = Vec::new();
+        Self::collect_ends(start, &mut walked, ranking, &mut found);
+
+        found.sort_by(|(w1, s1), (w2, s2)| s2.cmp(s1).then_with(|| w1.cmp(w2)));
+        found.truncate(k);
+        return found;
+    }
+
+    /// Compact binary encoding: node labels and postings are written in
+    /// preorder with varint lengths, doc ids delta-encoded per node.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = TRIEE_MAGIC.to_vec();
+        Self::encode_node(&self.root, &mut out);
+        return out;
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(bytes);
+        let triee = Self::decode_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(Error::IndexFormat("Trailing data after triee".to_owned()));
+        }
+        return Ok(triee);
+    }
+
+    pub fn decode_from(reader: &mut Reader) -> Result<Self, Error> {
+        if reader.bytes(TRIEE_MAGIC.len())? != TRIEE_MAGIC {
+            return Err(Error::IndexFormat("Not an encoded triee".to_owned()));
+        }
+        return Ok(Self { root: Self::decode_node(reader, 0)? });
+    }
+
+    fn encode_node(node: &TrieeNode, out: &mut Vec<u8>) {
+        binary::write_str(out, &node.label);
+
+        binary::write_varint(out, node.postings.len() as u64);
+        let mut previous = 0;
+        for (doc, count) in node.postings.iter() {
+            binary::write_varint(out, (*doc - previous) as u64);
+            binary::write_varint(out, *count as u64);
+            previous = *doc;
+        }
+
+        binary::write_varint(out, node.children.len() as u64);
+        for child in node.children.iter() {
+            Self::encode_node(child, out);
+        }
     }
 
-    fn ptrint_end_nodes(starting_node: &TrieeNode, chars: Vec<char>) {
-        if starting_node.is_end {
-            println!("{}", chars.iter().collect::<String>());
-            for p in starting_node.paths.iter() {
-                println!("   {}", p.display());
+    /// Checks what the rest of `Triee` relies on: ids that fit and ascend,
+    /// counts above zero, and children with non-empty labels sorted by their first char.
+    fn decode_node(reader: &mut Reader, depth: usize) -> Result<TrieeNode, Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::IndexFormat("Triee nested too deep".to_owned()));
+        }
+        let mut node = TrieeNode::new(Box::from(reader.str()?));
+        if depth > 0 && node.label.is_empty() {
+            return Err(Error::IndexFormat("Empty triee label".to_owned()));
+        }
+
+        let mut previous: Option<DocId> = None;
+        for _ in 0..reader.varint()? {
+            let gap = DocId::try_from(reader.varint()?).ok();
+            let doc = match (previous, gap) {
+                (None, Some(gap)) => Some(gap),
+                (Some(previous), Some(gap)) if gap > 0 => previous.checked_add(gap),
+                _ => None,
+            };
+            let doc = doc.ok_or_else(|| Error::IndexFormat("Invalid doc id in triee postings".to_owned()))?;
+            let count = u32::try_from(reader.varint()?).ok()
+                .filter(|count| *count > 0)
+                .ok_or_else(|| Error::IndexFormat("Invalid count in triee postings".to_owned()))?;
+            node.postings.push((doc, count));
+            previous = Some(doc);
+        }
+
+        for _ in 0..reader.varint()? {
+            let child = Self::decode_node(reader, depth + 1)?;
+            if node.children.last().is_some_and(|last| last.first_char() >= child.first_char()) {
+                return Err(Error::IndexFormat("Triee children out of order".to_owned()));
+            }
+            node.children.push(child);
+        }
+        return Ok(node);
+    }
+
+    pub fn print_ends(&self, documents: &DocumentRegistry) {
+        Self::ptrint_end_nodes(&self.root, String::new(), documents);
+    }
+
+    fn ptrint_end_nodes(starting_node: &TrieeNode, word: String, documents: &DocumentRegistry) {
+        if starting_node.is_end() {
+            println!("{}", word);
+            for (doc, _) in starting_node.postings.iter() {
+                println!("   {}", documents.path(*doc).display());
             }
         }
 
-        for (letter, node) in starting_node.children.iter() {
-            let mut v = chars.clone();
-            v.push(*letter);
-            Self::ptrint_end_nodes(node, v);
+        for node in starting_node.children.iter() {
+            Self::ptrint_end_nodes(node, word.clone() + &node.label, documents);
         }
     }
 
-    fn paths_for_prefix_static(starting_node: &'a TrieeNode, word: &[char]) -> Option<&'a HashSet<PathBuf>> {
-        if word.is_empty() {
-            return Some(&starting_node.paths);
+    fn collect_ends(node: &TrieeNode, word: &mut String, ranking: Ranking, found: &mut Vec<(String, usize)>) {
+        if node.is_end() {
+            let score = match ranking {
+                Ranking::DocumentFrequency => node.postings.len(),
+                Ranking::TotalFrequency => node.postings.iter().map(|(_, c)| *c as usize).sum(),
+            };
+            found.push((word.clone(), score));
         }
 
-        if !starting_node.children.contains_key(&word[0]) {
-            return None;
+        for child in node.children.iter() {
+            let len = word.len();
+            word.push_str(&child.label);
+            Self::collect_ends(child, word, ranking, found);
+            word.truncate(len);
+        }
+    }
+
+    /// Finds the node whose subtree holds every word starting with `word`,
+    /// together with the full key leading to it. The prefix may end in the
+    /// middle of an edge label, in which case the node below that edge is returned.
+    fn node_for_prefix(starting_node: &'a TrieeNode, word: &str) -> Option<(&'a TrieeNode, String)> {
+        let mut node = starting_node;
+        let mut rest = word;
+        let mut walked = String::new();
+
+        while let Some(letter) = rest.chars().next() {
+            let child = node.child(letter)?;
+            let common = common_prefix_len(&child.label, rest);
+
+            if common == rest.len() {
+                walked.push_str(&child.label);
+                return Some((child, walked));
+            }
+            if common < child.label.len() {
+                return None;
+            }
+
+            walked.push_str(&child.label);
+            rest = &rest[common..];
+            node = child;
         }
 
-        return Self::paths_for_prefix_static(
-            starting_node.children.get(&word[0]).unwrap(),
-            &word[1..]
-        );
+        return Some((node, walked));
+    }
+
+    fn remove_doc_static(starting_node: &mut TrieeNode, doc: DocId) -> bool {
+        let mut removed = starting_node.remove_posting(doc);
+        for child in starting_node.children.iter_mut() {
+            removed |= Self::remove_doc_static(child, doc);
+        }
 
+        if removed {
+            for pos in (0..starting_node.children.len()).rev() {
+                starting_node.tidy_child(pos);
+            }
+        }
+        return removed;
     }
 
-    fn insert_static(starting_node: &mut TrieeNode, word: &[char], found_at: PathBuf) {
+    fn remove_word_static(starting_node: &mut TrieeNode, word: &str) -> bool {
+        let letter = match word.chars().next() {
+            Some(letter) => letter,
+            None => {
+                let was_end = starting_node.is_end();
+                starting_node.postings.clear();
+                return was_end;
+            }
+        };
 
-        if word.is_empty() { return; }
+        let pos = match starting_node.child_position(letter) {
+            Ok(pos) => pos,
+            Err(_) => return false,
+        };
+
+        let child = &mut starting_node.children[pos];
+        let rest = match word.strip_prefix(&*child.label) {
+            Some(rest) => rest,
+            None => return false,
+        };
 
-        let (current_char, is_last) = (word[0], word.len() == 1);
+        let removed = Self::remove_word_static(child, rest);
+        if removed {
+            starting_node.tidy_child(pos);
+        }
+        return removed;
+    }
 
-        match (current_char, is_last) {
-            (letter, true) => {
-                starting_node.children.entry(letter)
-                    .and_modify(|v| {
-                        v.is_end = true;
-                        v.paths.insert(found_at.clone());
-                    })
-                    .or_insert(TrieeNode {
-                        is_end: true,
-                        children: HashMap::new(),
-                        paths: hash_set_of(found_at.clone())
-                    });
+    fn insert_static(starting_node: &mut TrieeNode, word: &str, found_at: DocId) {
+        let letter = match word.chars().next() {
+            Some(letter) => letter,
+            None => {
+                starting_node.add_posting(found_at);
+                return;
             }
-            (letter, false) => {
-                if starting_node.children.contains_key(&letter) {
-                    let dst_child = starting_node.children.get_mut(&letter).unwrap();
-                    dst_child.paths.insert(found_at.clone());
-                    Self::insert_static(dst_child, &word[1..], found_at)
-                } else {
-                    starting_node.children.insert(letter, TrieeNode {
-                        is_end: false,
-                        children: HashMap::new(),
-                        paths: hash_set_of(found_at.clone())
-                    });
-                    Self::insert_static(starting_node.children.get_mut(&letter).unwrap(), &word[1..], found_at);
+        };
+
+        match starting_node.child_position(letter) {
+            Err(pos) => {
+                let mut leaf = TrieeNode::new(Box::from(word));
+                leaf.add_posting(found_at);
+                starting_node.children.insert(pos, leaf);
+            }
+            Ok(pos) => {
+                let child = &mut starting_node.children[pos];
+                let common = common_prefix_len(&child.label, word);
+                if common < child.label.len() {
+                    child.split_at(common);
                 }
+                Self::insert_static(child, &word[common..], found_at);
             }
         }
     }
 }
 
-fn hash_set_of<T: Eq + std::hash::Hash>(val: T) -> HashSet<T> {
-    let mut t = HashSet::new();
-    t.insert(val);
-    t
+pub struct TrieeEntry<'t> {
+    pub word: String,
+    pub postings: &'t [(DocId, u32)],
+}
+
+#[allow(dead_code)]
+impl TrieeEntry<'_> {
+    pub fn document_frequency(&self) -> usize {
+        return self.postings.len();
+    }
+
+    pub fn total_frequency(&self) -> usize {
+        return self.postings.iter().map(|(_, c)| *c as usize).sum();
+    }
 }
 
+/// Depth-first walk over the end nodes. Children are kept sorted by their
+/// first char, so preorder yields words in lexicographic order.
+pub struct Iter<'t> {
+    stack: Vec<(&'t TrieeNode, usize)>,
+    word: String,
+}
+
+impl <'t> Iter<'t> {
+    fn new(start: &'t TrieeNode, prefix: String) -> Self {
+        let mut iter = Self { stack: Vec::new(), word: prefix };
+        iter.push_children(start);
+        if start.is_end() {
+            iter.stack.push((start, usize::MAX));
+        }
+        return iter;
+    }
+
+    fn empty() -> Self {
+        return Self { stack: Vec::new(), word: String::new() };
+    }
+
+    fn push_children(&mut self, node: &'t TrieeNode) {
+        let len = self.word.len();
+        for child in node.children.iter().rev() {
+            self.stack.push((child, len));
+        }
+    }
+}
+
+impl <'t> Iterator for Iter<'t> {
+    type Item = TrieeEntry<'t>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, len)) = self.stack.pop() {
+            // usize::MAX marks the starting node itself, whose key is already in `word`
+            if len != usize::MAX {
+                self.word.truncate(len);
+                self.word.push_str(&node.label);
+                self.push_children(node);
+            }
+
+            if node.is_end() {
+                return Some(TrieeEntry { word: self.word.clone(), postings: &node.postings });
+            }
+        }
+        return None;
+    }
+}
+
+/// Length in bytes of the longest common prefix, always on a char boundary.
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    return a.chars()
+        .zip(b.chars())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x.len_utf8())
+        .sum();
+}
+
+#[derive(Default)]
 struct TrieeNode {
-    is_end: bool,
-    children: HashMap<char, TrieeNode>,
-    paths: HashSet<PathBuf>,
+    label: Box<str>,
+    children: Vec<TrieeNode>,
+    postings: Vec<(DocId, u32)>,
 }
 
 impl TrieeNode {
-    fn new() -> Self {
+    fn new(label: Box<str>) -> Self {
         return TrieeNode {
-            is_end: false,
-            children: HashMap::new(),
-            paths: HashSet::new(),
+            label,
+            children: Vec::new(),
+            postings: Vec::new(),
+        };
+    }
+
+    fn is_end(&self) -> bool {
+        return !self.postings.is_empty();
+    }
+
+    fn first_char(&self) -> char {
+        return self.label.chars().next().unwrap_or('\0');
+    }
+
+    fn child_position(&self, letter: char) -> Result<usize, usize> {
+        return self.children.binary_search_by(|c| c.first_char().cmp(&letter));
+    }
+
+    fn child(&self, letter: char) -> Option<&TrieeNode> {
+        return self.child_position(letter).ok().map(|pos| &self.children[pos]);
+    }
+
+    fn add_posting(&mut self, doc: DocId) {
+        match self.postings.binary_search_by_key(&doc, |(d, _)| *d) {
+            Ok(pos) => self.postings[pos].1 += 1,
+            Err(pos) => self.postings.insert(pos, (doc, 1)),
+        }
+    }
+
+    fn remove_posting(&mut self, doc: DocId) -> bool {
+        return match self.postings.binary_search_by_key(&doc, |(d, _)| *d) {
+            Ok(pos) => {
+                self.postings.remove(pos);
+                true
+            }
+            Err(_) => false,
+        };
+    }
+
+    /// Restores the radix invariants for a child after a removal: a child that
+    /// ends no word and has no children is dropped, and one that ends no word
+    /// but has a single child is merged with it.
+    fn tidy_child(&mut self, pos: usize) {
+        let child = &mut self.children[pos];
+        if child.is_end() {
+            return;
+        }
+
+        match child.children.len() {
+            0 => {
+                self.children.remove(pos);
+            }
+            1 => {
+                let grandchild = child.children.pop().unwrap();
+                child.label = (child.label.to_string() + &grandchild.label).into_boxed_str();
+                child.children = grandchild.children;
+                child.postings = grandchild.postings;
+            }
+            _ => {}
+        }
+    }
+
+    /// Splits the edge label at byte `byte`, moving the tail, children and
+    /// postings into a single new child.
+    fn split_at(&mut self, byte: usize) {
+        let tail = TrieeNode {
+            label: Box::from(&self.label[byte..]),
+            children: std::mem::take(&mut self.children),
+            postings: std::mem::take(&mut self.postings),
         };
+        self.label = Box::from(&self.label[..byte]);
+        self.children = vec![tail];
+    }
+
+    fn count_ends(&self) -> usize {
+        let below: usize = self.children.iter().map(TrieeNode::count_ends).sum();
+        return below + self.is_end() as usize;
+    }
+
+    fn collect_docs(&self, docs: &mut BTreeSet<DocId>) {
+        docs.extend(self.postings.iter().map(|(d, _)| *d));
+        for child in self.children.iter() {
+            child.collect_docs(docs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triee(words: &[(&str, DocId)]) -> Triee {
+        let mut triee = Triee::new();
+        for (word, doc) in words {
+            triee.insert_word(word, *doc);
+        }
+        return triee;
+    }
+
+    fn owned(words: &[(&str, usize)]) -> Vec<(String, usize)> {
+        return words.iter().map(|(w, n)| (w.to_string(), *n)).collect();
+    }
+
+    #[test]
+    fn complete_ranks_by_document_frequency() {
+        // "card" is in three documents once each, "care" in one document four times
+        let triee = triee(&[
+            ("card", 1), ("card", 2), ("card", 3),
+            ("care", 1), ("care", 1), ("care", 1), ("care", 1),
+            ("cart", 2), ("cart", 3),
+            ("dog", 1),
+        ]);
+
+        assert_eq!(triee.complete("car", 10, Ranking::DocumentFrequency), owned(&[("card", 3), ("cart", 2), ("care", 1)]));
+        assert_eq!(triee.complete("car", 10, Ranking::TotalFrequency), owned(&[("care", 4), ("card", 3), ("cart", 2)]));
+    }
+
+    #[test]
+    fn complete_truncates_to_k() {
+        let triee = triee(&[("ab", 1), ("ab", 2), ("ac", 1), ("ad", 1), ("ad", 2), ("ad", 3)]);
+
+        assert_eq!(triee.complete("a", 2, Ranking::DocumentFrequency), owned(&[("ad", 3), ("ab", 2)]));
+        assert_eq!(triee.complete("a", 1, Ranking::TotalFrequency), owned(&[("ad", 3)]));
+        assert!(triee.complete("a", 0, Ranking::DocumentFrequency).is_empty());
+    }
+
+    #[test]
+    fn complete_breaks_ties_alphabetically() {
+        let triee = triee(&[("tab", 1), ("tea", 2), ("tax", 3), ("te", 1), ("tub", 1), ("tub", 1)]);
+
+        assert_eq!(triee.complete("t", 10, Ranking::DocumentFrequency), owned(&[("tab", 1), ("tax", 1), ("te", 1), ("tea", 1), ("tub", 1)]));
+        assert_eq!(triee.complete("t", 10, Ranking::TotalFrequency), owned(&[("tub", 2), ("tab", 1), ("tax", 1), ("te", 1), ("tea", 1)]));
+    }
+
+    #[test]
+    fn complete_matches_inside_edge_labels() {
+        let triee = triee(&[("alphabet", 1), ("alpine", 2), ("beta", 1)]);
+
+        assert_eq!(triee.complete("alph", 10, Ranking::DocumentFrequency), owned(&[("alphabet", 1)]));
+        assert_eq!(triee.complete("alpha", 10, Ranking::DocumentFrequency), owned(&[("alphabet", 1)]));
+        assert_eq!(triee.complete("", 10, Ranking::DocumentFrequency).len(), 3);
+        assert!(triee.complete("alphax", 10, Ranking::DocumentFrequency).is_empty());
+        assert!(triee.complete("gamma", 10, Ranking::DocumentFrequency).is_empty());
+    }
+
+    #[test]
+    fn iter_and_prefixes_walk_words_in_order() {
+        let triee = triee(&[("beta", 2), ("alpha", 1), ("alphabet", 1), ("alphabet", 3), ("alp", 2), ("gamma", 1)]);
+
+        assert_eq!(triee.len(), 5);
+        assert_eq!(triee.words().collect::<Vec<_>>(), ["alp", "alpha", "alphabet", "beta", "gamma"]);
+        let entries = triee.iter().map(|e| (e.word, e.postings.to_vec())).collect::<Vec<_>>();
+        assert_eq!(entries[2], ("alphabet".to_owned(), vec![(1, 1), (3, 1)]));
+
+        assert_eq!(triee.words_with_prefix("alph").collect::<Vec<_>>(), ["alpha", "alphabet"]);
+        assert_eq!(triee.words_with_prefix("alp").collect::<Vec<_>>(), ["alp", "alpha", "alphabet"]);
+        assert_eq!(triee.words_with_prefix("b").collect::<Vec<_>>(), ["beta"]);
+        assert_eq!(triee.words_with_prefix("").count(), 5);
+        assert_eq!(triee.words_with_prefix("delta").count(), 0);
+    }
+
+    #[test]
+    fn encode_and_decode_roundtrip() {
+        let mut triee = triee(&[("naïve", 1), ("nail", 7), ("nail", 7), ("nail", 300), ("zebra", 0), ("zero", 2)]);
+        triee.remove_word("zero");
+        let decoded = Triee::decode(&triee.encode()).unwrap();
+
+        let entries = |t: &Triee| t.iter().map(|e| (e.word, e.postings.to_vec())).collect::<Vec<_>>();
+        assert_eq!(entries(&decoded), entries(&triee));
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded.postings("nail"), Some(&[(7, 2), (300, 1)][..]));
+        assert_eq!(decoded.complete("na", 1, Ranking::TotalFrequency), owned(&[("nail", 3)]));
+        assert!(Triee::decode(&Triee::new().encode()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated_and_corrupt_data_are_errors() {
+        let encoded = triee(&[("alpha", 1), ("beta", 2)]).encode();
+        for end in 0..encoded.len() {
+            assert!(Triee::decode(&encoded[..end]).is_err(), "truncated at {}", end);
+        }
+        assert!(Triee::decode(&[encoded.as_slice(), &[0]].concat()).is_err());
+
+        // magic, then an empty root label, no postings and one child with `body`
+        let with_child = |body: &[u8]| [TRIEE_MAGIC, &[0, 0, 1], body].concat();
+        let errors = [
+            // a doc id overflowing `DocId`
+            with_child(&[1, b'a', 2, 0xff, 0xff, 0xff, 0xff, 0x0f, 1, 1, 1, 0]),
+            // a repeated doc id
+            with_child(&[1, b'a', 2, 4, 1, 0, 1, 0]),
+            // a count of zero
+            with_child(&[1, b'a', 1, 4, 0, 0]),
+            // a child without a label
+            with_child(&[1, b'a', 1, 4, 1, 1, 0, 1, 5, 1, 0]),
+        ];
+        for blob in errors {
+            assert!(matches!(Triee::decode(&blob), Err(Error::IndexFormat(_))), "{:?}", blob);
+        }
+
+        // two children starting with the same char
+        let twins = [TRIEE_MAGIC, &[0, 0, 2], &[1, b'a', 1, 4, 1, 0], &[2, b'a', b'b', 1, 4, 1, 0]].concat();
+        assert!(matches!(Triee::decode(&twins), Err(Error::IndexFormat(_))));
+
+        // a chain of single children deeper than any real triee
+        let mut deep = TRIEE_MAGIC.to_vec();
+        deep.extend([0, 0, 1]);
+        for _ in 0..MAX_DEPTH + 1 {
+            deep.extend([1, b'a', 0, 1]);
+        }
+        assert!(matches!(Triee::decode(&deep), Err(Error::IndexFormat(_))));
     }
 }